@@ -5,6 +5,7 @@ extern crate bitcoin_rpc;
 #[macro_use]
 extern crate failure;
 
+use bitcoin_rpc::hashes::Txid;
 use bitcoin_rpc::BitcoinRpc;
 
 type Result<T> = std::result::Result<T, failure::Error>;
@@ -35,7 +36,7 @@ fn main() -> Result<()> {
         "best block hash by `get`: {}",
         bitcoin_block.header.prev_blockhash
     );
-    let bitcoin_tx: bitcoin::Transaction = rpc.get(&bitcoin_block.txdata[0].txid())?;
+    let bitcoin_tx: bitcoin::Transaction = rpc.get(&Txid::from(bitcoin_block.txdata[0].txid()))?;
     println!("tx by `get`: {}", bitcoin_tx.txid());
 
     Ok(())
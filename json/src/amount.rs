@@ -0,0 +1,453 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Satoshi-precise amounts and fee rates.
+//!
+//! Amounts are (de)serialized as the BTC decimal strings bitcoind uses,
+//! e.g. `"0.00010000"`, which bitcoind accepts wherever it takes an amount.
+
+use std::error;
+use std::fmt::{self, Formatter};
+use std::iter;
+use std::ops;
+use std::str::FromStr;
+
+use serde::{de, ser};
+
+/// Number of satoshis in one bitcoin.
+pub const COIN: i64 = 100_000_000;
+
+/// An amount of bitcoin, stored as satoshis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct Amount(i64);
+
+impl Amount {
+    /// Creates an amount from satoshis.
+    pub fn from_sat(sat: i64) -> Amount {
+        Amount(sat)
+    }
+
+    /// The amount in satoshis.
+    pub fn as_sat(&self) -> i64 {
+        self.0
+    }
+
+    /// Checked addition, `None` on overflow.
+    pub fn checked_add(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_add(rhs.0).map(Amount)
+    }
+
+    /// Checked subtraction, `None` on overflow.
+    pub fn checked_sub(self, rhs: Amount) -> Option<Amount> {
+        self.0.checked_sub(rhs.0).map(Amount)
+    }
+
+    /// Whether the amount is negative.
+    pub fn is_negative(&self) -> bool {
+        self.0 < 0
+    }
+}
+
+impl fmt::Display for Amount {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = (self.0 as i128).abs();
+        write!(
+            f,
+            "{}{}.{:08}",
+            sign,
+            abs / COIN as i128,
+            abs % COIN as i128
+        )
+    }
+}
+
+impl FromStr for Amount {
+    type Err = ParseAmountError;
+
+    /// Parses a BTC decimal string with at most 8 decimals.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, s) = match s.strip_prefix('-') {
+            Some(s) => (true, s),
+            None => (false, s),
+        };
+
+        let mut parts = s.splitn(2, '.');
+        let whole = parts.next().unwrap_or("");
+        let fraction = parts.next().unwrap_or("");
+
+        let is_digits = |s: &str| s.bytes().all(|b| b.is_ascii_digit());
+        if (whole.is_empty() && fraction.is_empty()) || !is_digits(whole) || !is_digits(fraction) {
+            return Err(ParseAmountError::InvalidFormat);
+        }
+        if fraction.len() > 8 {
+            return Err(ParseAmountError::TooPrecise);
+        }
+
+        let mut sat: i64 = 0;
+        for b in whole.bytes().chain(fraction.bytes()) {
+            sat = sat
+                .checked_mul(10)
+                .and_then(|sat| sat.checked_add(i64::from(b - b'0')))
+                .ok_or(ParseAmountError::TooBig)?;
+        }
+        for _ in fraction.len()..8 {
+            sat = sat.checked_mul(10).ok_or(ParseAmountError::TooBig)?;
+        }
+
+        Ok(Amount(if negative { -sat } else { sat }))
+    }
+}
+
+impl ops::Add for Amount {
+    type Output = Amount;
+
+    fn add(self, rhs: Amount) -> Amount {
+        Amount(self.0 + rhs.0)
+    }
+}
+
+impl ops::Sub for Amount {
+    type Output = Amount;
+
+    fn sub(self, rhs: Amount) -> Amount {
+        Amount(self.0 - rhs.0)
+    }
+}
+
+impl ops::AddAssign for Amount {
+    fn add_assign(&mut self, rhs: Amount) {
+        self.0 += rhs.0;
+    }
+}
+
+impl ops::SubAssign for Amount {
+    fn sub_assign(&mut self, rhs: Amount) {
+        self.0 -= rhs.0;
+    }
+}
+
+impl ops::Neg for Amount {
+    type Output = Amount;
+
+    fn neg(self) -> Amount {
+        Amount(-self.0)
+    }
+}
+
+impl iter::Sum for Amount {
+    fn sum<I: Iterator<Item = Amount>>(iter: I) -> Amount {
+        Amount(iter.map(|a| a.0).sum())
+    }
+}
+
+impl<'de> de::Deserialize<'de> for Amount {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = Amount;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                write!(fmt, "a BTC amount")
+            }
+
+            fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                // `Display` of a float never uses an exponent and is the
+                // shortest decimal that parses back to it, i.e. the decimal
+                // bitcoind sent (`1e-8` is shown as "0.00000001").
+                Amount::from_str(&v.to_string()).map_err(de::Error::custom)
+            }
+
+            fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                v.checked_mul(COIN)
+                    .map(Amount)
+                    .ok_or_else(|| de::Error::custom(ParseAmountError::TooBig))
+            }
+
+            fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                if v > i64::MAX as u64 {
+                    return Err(de::Error::custom(ParseAmountError::TooBig));
+                }
+                self.visit_i64(v as i64)
+            }
+
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+            where
+                E: de::Error,
+            {
+                Amount::from_str(v).map_err(de::Error::custom)
+            }
+        }
+
+        deserializer.deserialize_any(Visitor)
+    }
+}
+
+impl ser::Serialize for Amount {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// A fee rate, stored as satoshis per 1000 virtual bytes.
+///
+/// (De)serialized as BTC/kB, the unit bitcoind uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub struct FeeRate(u64);
+
+impl FeeRate {
+    /// Creates a fee rate from satoshis per 1000 virtual bytes.
+    pub fn from_sat_per_kvb(sat_per_kvb: u64) -> FeeRate {
+        FeeRate(sat_per_kvb)
+    }
+
+    /// Creates a fee rate from whole satoshis per virtual byte, saturating
+    /// at the largest representable rate.
+    pub fn from_sat_per_vb(sat_per_vb: u64) -> FeeRate {
+        FeeRate(sat_per_vb.saturating_mul(1000))
+    }
+
+    /// The fee rate in satoshis per 1000 virtual bytes.
+    pub fn sat_per_kvb(&self) -> u64 {
+        self.0
+    }
+
+    /// The fee rate in satoshis per virtual byte.
+    pub fn sat_per_vb(&self) -> f64 {
+        self.0 as f64 / 1000.0
+    }
+
+    /// The fee for `vsize` virtual bytes, rounded up and saturating at the
+    /// largest representable amount.
+    pub fn fee(&self, vsize: u64) -> Amount {
+        let sat = self
+            .0
+            .checked_mul(vsize)
+            .map(|sat_k| sat_k / 1000 + u64::from(sat_k % 1000 != 0))
+            .unwrap_or(u64::MAX);
+        Amount(sat.min(i64::MAX as u64) as i64)
+    }
+
+    /// The fee rate paying `fee` for `vsize` virtual bytes, rounded down.
+    pub fn from_fee(fee: Amount, vsize: u64) -> FeeRate {
+        FeeRate(fee.as_sat().max(0) as u64 * 1000 / vsize.max(1))
+    }
+}
+
+impl fmt::Display for FeeRate {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}.{:03} sat/vB", self.0 / 1000, self.0 % 1000)
+    }
+}
+
+impl<'de> de::Deserialize<'de> for FeeRate {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        let per_kvb = Amount::deserialize(deserializer)?;
+        if per_kvb.is_negative() {
+            return Err(de::Error::custom("negative fee rate"));
+        }
+
+        Ok(FeeRate(per_kvb.as_sat() as u64))
+    }
+}
+
+impl ser::Serialize for FeeRate {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        Amount(self.0 as i64).serialize(serializer)
+    }
+}
+
+/// (De)serializes an optional `FeeRate` as a number of sat/vB, as taken by
+/// newer RPCs such as "bumpfee", for use with `#[serde(with = "...")]`.
+pub mod opt_sat_per_vb {
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    use super::FeeRate;
+
+    pub fn serialize<S>(v: &Option<FeeRate>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *v {
+            Some(ref feerate) => serializer.serialize_some(&feerate.sat_per_vb()),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<FeeRate>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v: Option<f64> = Option::deserialize(deserializer)?;
+        Ok(v.map(|v| FeeRate::from_sat_per_kvb((v * 1000.0).round() as u64)))
+    }
+}
+
+/// An error parsing an `Amount`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParseAmountError {
+    /// Not a decimal number
+    InvalidFormat,
+    /// More than 8 decimals
+    TooPrecise,
+    /// Doesn't fit in 64 bits of satoshis
+    TooBig,
+}
+
+impl fmt::Display for ParseAmountError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            ParseAmountError::InvalidFormat => "invalid amount format",
+            ParseAmountError::TooPrecise => "amount has more than 8 decimals",
+            ParseAmountError::TooBig => "amount is too big",
+        })
+    }
+}
+
+impl error::Error for ParseAmountError {}
+
+#[cfg(test)]
+mod tests {
+    use serde_json;
+
+    use super::{Amount, FeeRate, ParseAmountError, COIN};
+
+    #[test]
+    fn parse() {
+        let cases = vec![
+            ("21000000", Ok(21_000_000 * COIN)),
+            ("21000000.00000000", Ok(21_000_000 * COIN)),
+            ("0.00000001", Ok(1)),
+            (".00000001", Ok(1)),
+            ("1.", Ok(COIN)),
+            ("0.1", Ok(10_000_000)),
+            ("-0.00000001", Ok(-1)),
+            ("-21000000", Ok(-21_000_000 * COIN)),
+            ("92233720368.54775807", Ok(i64::MAX)),
+            ("92233720368.54775808", Err(ParseAmountError::TooBig)),
+            ("0.000000001", Err(ParseAmountError::TooPrecise)),
+            ("1.123456789", Err(ParseAmountError::TooPrecise)),
+            ("", Err(ParseAmountError::InvalidFormat)),
+            ("-", Err(ParseAmountError::InvalidFormat)),
+            (".", Err(ParseAmountError::InvalidFormat)),
+            ("--1", Err(ParseAmountError::InvalidFormat)),
+            ("1e-8", Err(ParseAmountError::InvalidFormat)),
+            ("1.0.0", Err(ParseAmountError::InvalidFormat)),
+            (" 1", Err(ParseAmountError::InvalidFormat)),
+        ];
+        for (s, expected) in cases {
+            assert_eq!(s.parse::<Amount>().map(|a| a.as_sat()), expected, "{}", s);
+        }
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Amount::from_sat(0).to_string(), "0.00000000");
+        assert_eq!(Amount::from_sat(1).to_string(), "0.00000001");
+        assert_eq!(Amount::from_sat(-1).to_string(), "-0.00000001");
+        assert_eq!(Amount::from_sat(-COIN).to_string(), "-1.00000000");
+        assert_eq!(
+            Amount::from_sat(21_000_000 * COIN).to_string(),
+            "21000000.00000000"
+        );
+        assert_eq!(
+            Amount::from_sat(i64::MIN).to_string(),
+            "-92233720368.54775808"
+        );
+    }
+
+    #[test]
+    fn deserialize() {
+        let cases = vec![
+            ("21000000", 21_000_000 * COIN),
+            ("21000000.0", 21_000_000 * COIN),
+            ("2.1e7", 21_000_000 * COIN),
+            ("0.00000001", 1),
+            ("1e-8", 1),
+            ("1E-8", 1),
+            ("-1e-8", -1),
+            ("-0.5", -COIN / 2),
+            ("0.1", 10_000_000),
+            ("0.3", 30_000_000),
+            ("20999999.97690000", 2_099_999_997_690_000),
+            ("\"0.00010000\"", 10_000),
+            ("\"-0.00000001\"", -1),
+        ];
+        for (json, sat) in cases {
+            let amount: Amount = serde_json::from_str(json).unwrap();
+            assert_eq!(amount.as_sat(), sat, "{}", json);
+        }
+
+        assert!(serde_json::from_str::<Amount>("1e-9").is_err());
+        assert!(serde_json::from_str::<Amount>("0.123456789").is_err());
+        assert!(serde_json::from_str::<Amount>("1e20").is_err());
+        assert!(serde_json::from_str::<Amount>("\"1e-8\"").is_err());
+        assert!(serde_json::from_str::<Amount>("null").is_err());
+    }
+
+    #[test]
+    fn serialize() {
+        for &sat in &[0, 1, -1, COIN, 21_000_000 * COIN, i64::MAX] {
+            let amount = Amount::from_sat(sat);
+            let json = serde_json::to_string(&amount).unwrap();
+            assert_eq!(json, format!("\"{}\"", amount));
+            assert_eq!(serde_json::from_str::<Amount>(&json).unwrap(), amount);
+        }
+    }
+
+    #[test]
+    fn fee_rate() {
+        let rate: FeeRate = serde_json::from_str("0.00001").unwrap();
+        assert_eq!(rate, FeeRate::from_sat_per_vb(1));
+        assert_eq!(rate.fee(141), Amount::from_sat(141));
+        assert_eq!(
+            FeeRate::from_sat_per_kvb(1001).fee(1000),
+            Amount::from_sat(1001)
+        );
+        assert_eq!(FeeRate::from_sat_per_kvb(1001).fee(1), Amount::from_sat(2));
+        assert!(serde_json::from_str::<FeeRate>("-0.00001").is_err());
+    }
+
+    #[test]
+    fn fee_rate_overflow() {
+        assert_eq!(
+            FeeRate::from_sat_per_vb(u64::MAX),
+            FeeRate::from_sat_per_kvb(u64::MAX)
+        );
+        assert_eq!(
+            FeeRate::from_sat_per_kvb(u64::MAX).fee(2),
+            Amount::from_sat(i64::MAX)
+        );
+        assert_eq!(
+            FeeRate::from_sat_per_kvb(u64::MAX / 2).fee(1),
+            Amount::from_sat(i64::MAX / 1000 + 1)
+        );
+    }
+}
@@ -1,44 +1,76 @@
 //! Blockchain related RPC result types.
 
-use bitcoin::util::hash::Sha256dHash;
-use strason::Json;
+use std::collections::HashMap;
+
+use bitcoin::network::constants::Network as AddressNetwork;
+use serde::de::{self, DeserializeOwned};
+
+use amount::{Amount, FeeRate};
+use hashes::{BlockHash, TxMerkleRoot, Txid, Wtxid};
+use mining::EstimateMode;
+use Extra;
 
 /// Models the result of "waitfornewblock", and "waitforblock"
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BlockRef {
-    pub hash: Sha256dHash,
+    pub hash: BlockHash,
     pub height: u64,
 }
 
-impl From<SerdeBlockRef> for BlockRef {
-    fn from(v: SerdeBlockRef) -> BlockRef {
-        BlockRef {
-            hash: Sha256dHash::from_hex(&v.hash).unwrap(),
-            height: v.height,
-        }
+string_enum! {
+    /// The chain a node is on, as named in "getblockchaininfo"
+    pub enum Network {
+        /// Mainnet
+        Bitcoin => "main",
+        /// Testnet3
+        Testnet => "test",
+        /// Testnet4 (28.0+)
+        Testnet4 => "testnet4",
+        /// Signet
+        Signet => "signet",
+        /// Regtest
+        Regtest => "regtest",
     }
 }
 
-#[doc(hidden)]
-#[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct SerdeBlockRef {
-    pub hash: String,
-    pub height: u64,
+impl Network {
+    /// The magic starting P2P messages and `blk*.dat` records, unknown for
+    /// unknown chains. For signet, that of the default signet.
+    pub fn magic(&self) -> Option<u32> {
+        match *self {
+            Network::Bitcoin => Some(0xD9B4_BEF9),
+            Network::Testnet => Some(0x0709_110B),
+            Network::Testnet4 => Some(0x283F_161C),
+            Network::Signet => Some(0x40CF_030A),
+            Network::Regtest => Some(0xDAB5_BFFA),
+            Network::Unknown(_) => None,
+        }
+    }
+
+    /// The network `bitcoin::Address` parses addresses of this chain as,
+    /// unknown for unknown chains. Testnet4 and signet share the address
+    /// prefixes of testnet.
+    pub fn address_network(&self) -> Option<AddressNetwork> {
+        match *self {
+            Network::Bitcoin => Some(AddressNetwork::Bitcoin),
+            Network::Testnet | Network::Testnet4 | Network::Signet => Some(AddressNetwork::Testnet),
+            Network::Regtest => Some(AddressNetwork::Regtest),
+            Network::Unknown(_) => None,
+        }
+    }
 }
 
 /// Models the result of "getblockchaininfo"
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct BlockchainInfo {
-    // TODO: Use Network from rust-bitcoin
-    /// Current network name as defined in BIP70 (main, test, regtest)
-    pub chain: String,
+    /// Current network
+    pub chain: Network,
     /// The current number of blocks processed in the server
     pub blocks: u64,
     /// The current number of headers we have validated
     pub headers: u64,
-    // TODO: Use Sha256dHash from rust-bitcoin
     /// The hash of the currently best block
-    pub bestblockhash: String,
+    pub bestblockhash: BlockHash,
     /// The current difficulty
     pub difficulty: f64,
     /// Median time for the current best block
@@ -49,8 +81,8 @@ pub struct BlockchainInfo {
     pub initialblockdownload: bool,
     /// Total amount of work in active chain, in hexadecimal
     pub chainwork: String,
-    /// The estimated size of the block and undo files on disk
-    pub size_on_disk: u64,
+    /// The estimated size of the block and undo files on disk (0.17+)
+    pub size_on_disk: Option<u64>,
     /// If the blocks are subject to pruning
     pub pruned: bool,
     /// Lowest-height complete block stored (only present if pruning is enabled)
@@ -59,79 +91,529 @@ pub struct BlockchainInfo {
     pub automatic_pruning: Option<bool>,
     /// The target size used by pruning (only present if automatic pruning is enabled)
     pub prune_target_size: Option<u64>,
-    /// Status of softforks in progress
-    pub softforks: Vec<Softfork>,
-    // TODO: add a type?
-    /// Status of BIP9 softforks in progress
-    pub bip9_softforks: Json,
-    /// Any network and blockchain warnings.
-    pub warnings: String,
+    /// Status of softforks (removed on 23.0+, see "getdeploymentinfo")
+    #[serde(default, deserialize_with = "deserialize_softforks")]
+    pub softforks: HashMap<String, Deployment>,
+    /// Status of BIP9 softforks (only present before 0.19, merged into
+    /// `softforks` since)
+    #[serde(default)]
+    pub bip9_softforks: HashMap<String, Bip9Info>,
+    /// Any network and blockchain warnings
+    #[serde(deserialize_with = "::deserialize_warnings")]
+    pub warnings: Vec<String>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-/// Models the result of "getblockchaininfo"
+impl BlockchainInfo {
+    /// The deployments in `softforks` and `bip9_softforks`.
+    pub fn deployments(&self) -> HashMap<String, Deployment> {
+        let mut deployments = self.softforks.clone();
+        for (name, bip9) in &self.bip9_softforks {
+            deployments.insert(
+                name.clone(),
+                Deployment {
+                    deployment_type: DeploymentType::Bip9,
+                    active: bip9.status == Bip9Status::Active,
+                    height: None,
+                    bip9: Some(bip9.clone()),
+                    extra: Extra::new(),
+                },
+            );
+        }
+        deployments
+    }
+}
+
+/// Models the result of "getdeploymentinfo" (23.0+)
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DeploymentInfo {
+    /// The block the deployment states are for
+    pub hash: BlockHash,
+    /// The height of the block
+    pub height: u64,
+    /// The deployments by name
+    pub deployments: HashMap<String, Deployment>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// The status of a soft fork deployment
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct BlockInfo {
-    hash: String,
-    confirmations: u64,
+pub struct Deployment {
+    /// How the deployment is activated
+    #[serde(rename = "type")]
+    pub deployment_type: DeploymentType,
+    /// Whether the rules are enforced for the next block
+    pub active: bool,
+    /// Height of the first block enforcing the rules (buried, or active
+    /// BIP9 deployments)
+    pub height: Option<u64>,
+    /// BIP9 signalling state (BIP9 deployments only)
+    pub bip9: Option<Bip9Info>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+string_enum! {
+    /// How a soft fork deployment is activated
+    pub enum DeploymentType {
+        /// Active from a hardcoded height
+        Buried => "buried",
+        /// Activated by miner signalling as defined in BIP9
+        Bip9 => "bip9",
+    }
+}
+
+/// The BIP9 state of a deployment
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Bip9Info {
+    /// The version bit used to signal (only present while started)
+    pub bit: Option<u8>,
+    /// The median time past from which the bit has meaning, -1 if always
+    /// active
+    #[serde(alias = "startTime")]
+    pub start_time: i64,
+    /// The median time past at which the deployment fails if not locked in
+    pub timeout: u64,
+    /// Minimum height of activation once locked in (0.21+)
+    pub min_activation_height: Option<u64>,
+    /// The state of the deployment
+    pub status: Bip9Status,
+    /// Height of the first block the status applies to
+    pub since: u64,
+    /// The state for the next block (23.0+)
+    pub status_next: Option<Bip9Status>,
+    /// Signalling statistics of the current period (only present while
+    /// started or locked in)
+    pub statistics: Option<Bip9Statistics>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+string_enum! {
+    /// The state of a BIP9 deployment
+    pub enum Bip9Status {
+        /// The start time isn't reached yet
+        Defined => "defined",
+        /// Miners are signalling
+        Started => "started",
+        /// The threshold was reached, activating after the period
+        LockedIn => "locked_in",
+        /// The rules are enforced
+        Active => "active",
+        /// The timeout was reached without locking in
+        Failed => "failed",
+    }
+}
+
+/// Signalling statistics of the current BIP9 period
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Bip9Statistics {
+    /// Length of the signalling period in blocks
+    pub period: u32,
+    /// Number of signalling blocks needed to lock in (only present while
+    /// started)
+    pub threshold: Option<u32>,
+    /// Number of blocks elapsed since the start of the period
+    pub elapsed: u32,
+    /// Number of signalling blocks in the period
+    pub count: u32,
+    /// Whether the threshold can still be reached in the period (only
+    /// present while started)
+    pub possible: Option<bool>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+impl Bip9Statistics {
+    /// Share of signalling blocks among those elapsed in the period.
+    pub fn signalling_ratio(&self) -> f64 {
+        if self.elapsed == 0 {
+            0.0
+        } else {
+            f64::from(self.count) / f64::from(self.elapsed)
+        }
+    }
+}
+
+/// The array of "softforks" before 0.19
+#[derive(Deserialize)]
+struct LegacySoftfork {
+    id: String,
+    reject: LegacyRejectStatus,
+}
+
+#[derive(Deserialize)]
+struct LegacyRejectStatus {
+    status: bool,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerdeSoftforks {
+    Map(HashMap<String, Deployment>),
+    List(Vec<LegacySoftfork>),
+}
+
+fn deserialize_softforks<'de, D>(deserializer: D) -> Result<HashMap<String, Deployment>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    Ok(match de::Deserialize::deserialize(deserializer)? {
+        SerdeSoftforks::Map(map) => map,
+        SerdeSoftforks::List(list) => list
+            .into_iter()
+            .map(|softfork| {
+                let deployment = Deployment {
+                    deployment_type: DeploymentType::Buried,
+                    active: softfork.reject.status,
+                    height: None,
+                    bip9: None,
+                    extra: Extra::new(),
+                };
+                (softfork.id, deployment)
+            })
+            .collect(),
+    })
+}
+
+/// Models the result of "getblock" with a verbosity of 1 or more
+///
+/// `T` is the type of the transactions, see `GetBlockVerbosity`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BlockInfo<T = Txid> {
+    /// The block hash
+    pub hash: BlockHash,
+    /// The number of confirmations, or -1 if the block is not on the main
+    /// chain
+    pub confirmations: i64,
+    /// The block size excluding witness data
     #[serde(rename = "strippedsize")]
-    stripped_size: u64,
-    size: u64,
-    weight: u64,
-    height: u64,
-    version: u64,
+    pub stripped_size: u64,
+    /// The block size
+    pub size: u64,
+    /// The block weight as defined in BIP 141
+    pub weight: u64,
+    /// The block height or index
+    pub height: u64,
+    /// The block version
+    pub version: i32,
+    /// The block version formatted in hexadecimal
     #[serde(rename = "versionHex")]
-    version_hex: String,
+    pub version_hex: String,
+    /// The merkle root
     #[serde(rename = "merkleroot")]
-    merkle_root: String,
-    tx: Vec<String>,
-    time: u64,
+    pub merkle_root: TxMerkleRoot,
+    /// The transactions
+    pub tx: Vec<T>,
+    /// The block time in seconds since epoch (Jan 1 1970 GMT)
+    pub time: u64,
+    /// The median block time in seconds since epoch (Jan 1 1970 GMT)
     #[serde(rename = "mediantime")]
-    median_time: u64,
-    nonce: u64,
-    bits: String,
-    difficulty: f64,
-    chainwork: String,
+    pub median_time: u64,
+    /// The nonce
+    pub nonce: u32,
+    /// The bits
+    pub bits: String,
+    /// The difficulty
+    pub difficulty: f64,
+    /// Expected number of hashes required to produce the chain up to this
+    /// block, in hexadecimal
+    pub chainwork: String,
+    /// The number of transactions in the block (only present on 0.17+)
     #[serde(rename = "nTx")]
-    n_tx: u32,
-    previousblockhash: String,
-    nextblockhash: String,
+    pub n_tx: Option<u32>,
+    /// The hash of the previous block (absent for the genesis block)
+    pub previousblockhash: Option<BlockHash>,
+    /// The hash of the next block (absent for the chain tip)
+    pub nextblockhash: Option<BlockHash>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// A verbosity of "getblock", selecting how transactions are returned.
+pub trait GetBlockVerbosity {
+    /// The verbosity passed to "getblock"
+    const LEVEL: u8;
+    /// The type of the transactions of the block
+    type Tx: DeserializeOwned;
 }
 
-/// Status of a softfork
+/// Verbosity 1: transactions are returned as txids.
+#[derive(Debug, Clone, Copy)]
+pub enum TxIds {}
+
+impl GetBlockVerbosity for TxIds {
+    const LEVEL: u8 = 1;
+    type Tx = Txid;
+}
+
+/// Verbosity 2: transactions are returned decoded.
+#[derive(Debug, Clone, Copy)]
+pub enum Transactions {}
+
+impl GetBlockVerbosity for Transactions {
+    const LEVEL: u8 = 2;
+    type Tx = DecodedTx;
+}
+
+/// Verbosity 3: transactions are returned decoded, along with the outputs
+/// spent by their inputs (only supported on 23.0+).
+#[derive(Debug, Clone, Copy)]
+pub enum TransactionsWithPrevouts {}
+
+impl GetBlockVerbosity for TransactionsWithPrevouts {
+    const LEVEL: u8 = 3;
+    type Tx = DecodedTx;
+}
+
+/// A decoded transaction
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct Softfork {
-    /// Name of softfork
-    pub id: String,
-    /// Block version
-    pub version: u64,
-    /// Progress toward rejecting pre-softfork blocks
-    pub reject: RejectStatus,
+pub struct DecodedTx {
+    /// The transaction id
+    pub txid: Txid,
+    /// The witness transaction id
+    pub hash: Wtxid,
+    /// The version
+    pub version: i32,
+    /// The serialized transaction size
+    pub size: u64,
+    /// The virtual transaction size as defined in BIP 141
+    pub vsize: u64,
+    /// The transaction weight as defined in BIP 141
+    pub weight: u64,
+    /// The lock time
+    pub locktime: u32,
+    /// The inputs
+    pub vin: Vec<DecodedTxIn>,
+    /// The outputs
+    pub vout: Vec<DecodedTxOut>,
+    /// The fee, if the spent outputs are known (only present on 0.21+)
+    pub fee: Option<Amount>,
+    /// The hex-encoded transaction
+    pub hex: Option<String>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-/// Progress toward rejecting pre-softfork blocks
+/// An input of a decoded transaction
 #[derive(Debug, Clone, Deserialize, Serialize)]
-pub struct RejectStatus {
-    /// `true` if threshold reached
-    pub status: bool,
+pub struct DecodedTxIn {
+    /// The hex-encoded coinbase data (coinbase only)
+    pub coinbase: Option<String>,
+    /// The id of the spent transaction (absent for the coinbase)
+    pub txid: Option<Txid>,
+    /// The index of the spent output (absent for the coinbase)
+    pub vout: Option<u32>,
+    /// The signature script (absent for the coinbase)
+    #[serde(rename = "scriptSig")]
+    pub script_sig: Option<ScriptSig>,
+    /// The hex-encoded witness items
+    pub txinwitness: Option<Vec<String>>,
+    /// The spent output (verbosity 3 only)
+    pub prevout: Option<PrevOut>,
+    /// The sequence number
+    pub sequence: u32,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// A signature script
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScriptSig {
+    /// The script in assembly
+    pub asm: String,
+    /// The hex-encoded script
+    pub hex: String,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// An output spent by an input of a decoded transaction
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PrevOut {
+    /// Whether the output was created by a coinbase
+    pub generated: bool,
+    /// The height of the block that created the output
+    pub height: u64,
+    /// The value of the output
+    pub value: Amount,
+    /// The output script
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: ScriptPubKey,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// An output of a decoded transaction
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct DecodedTxOut {
+    /// The value of the output
+    pub value: Amount,
+    /// The index of the output
+    pub n: u32,
+    /// The output script
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: ScriptPubKey,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// An output script
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ScriptPubKey {
+    /// The script in assembly
+    pub asm: String,
+    /// The hex-encoded script
+    pub hex: String,
+    /// The type of the script, e.g. "witness_v0_keyhash"
+    #[serde(rename = "type")]
+    pub script_type: String,
+    /// The address paid to, if any (only present on 22.0+)
+    pub address: Option<String>,
+    /// The addresses paid to (removed on 22.0+)
+    pub addresses: Option<Vec<String>>,
+    /// The number of required signatures (removed on 22.0+)
+    #[serde(rename = "reqSigs")]
+    pub req_sigs: Option<u32>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Models the result of "gettxout"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TxOutInfo {
+    /// The hash of the tip of the chain
+    pub bestblock: BlockHash,
+    /// The number of confirmations, 0 for a mempool transaction
+    pub confirmations: u32,
+    /// The value of the output
+    pub value: Amount,
+    /// The output script
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: ScriptPubKey,
+    /// Whether the output was created by a coinbase
+    pub coinbase: bool,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Models the result of "getmempoolinfo"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MempoolInfo {
+    /// Whether the mempool is fully loaded (only present on 0.19+)
+    pub loaded: Option<bool>,
+    /// Current transaction count
+    pub size: u64,
+    /// Sum of all virtual transaction sizes as defined in BIP 141
+    pub bytes: u64,
+    /// Total memory usage for the mempool
+    pub usage: u64,
+    /// Maximum memory usage for the mempool
+    pub maxmempool: u64,
+    /// Minimum fee rate for a transaction to be accepted
+    pub mempoolminfee: FeeRate,
+    /// Current minimum relay fee for transactions (only present on 0.16+)
+    pub minrelaytxfee: Option<FeeRate>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Models the result of "getmempoolentry"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MempoolEntry {
+    /// Virtual transaction size as defined in BIP 141 (only present on
+    /// 0.19+)
+    pub vsize: Option<u64>,
+    /// Transaction size (virtual size on 0.13 to 0.18, removed on 0.19+)
+    pub size: Option<u64>,
+    /// Transaction weight as defined in BIP 141 (only present on 0.20+)
+    pub weight: Option<u64>,
+    /// Transaction fee (removed on 0.20+, see `fees`)
+    pub fee: Option<Amount>,
+    /// Transaction fee with fee deltas used for mining priority (removed on
+    /// 0.20+, see `fees`)
+    pub modifiedfee: Option<Amount>,
+    /// Local time transaction entered pool in seconds since 1 Jan 1970 GMT
+    pub time: u64,
+    /// Block height when transaction entered pool
+    pub height: u64,
+    /// Number of in-mempool descendant transactions (including this one)
+    pub descendantcount: u64,
+    /// Virtual transaction size of in-mempool descendants (including this
+    /// one)
+    pub descendantsize: u64,
+    /// Number of in-mempool ancestor transactions (including this one)
+    pub ancestorcount: u64,
+    /// Virtual transaction size of in-mempool ancestors (including this
+    /// one)
+    pub ancestorsize: u64,
+    /// Hash of serialized transaction, including witness data
+    pub wtxid: Option<Wtxid>,
+    /// Fee information (only present on 0.19+)
+    pub fees: Option<MempoolEntryFees>,
+    /// Unconfirmed transactions used as inputs for this transaction
+    pub depends: Vec<Txid>,
+    /// Unconfirmed transactions spending outputs from this transaction
+    /// (only present on 0.17+)
+    pub spentby: Option<Vec<Txid>>,
+    /// Whether this transaction could be replaced due to BIP125
+    /// (only present on 0.19+)
+    #[serde(rename = "bip125-replaceable")]
+    pub bip125_replaceable: Option<bool>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Fee information of a mempool entry
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct MempoolEntryFees {
+    /// Transaction fee
+    pub base: Amount,
+    /// Transaction fee with fee deltas used for mining priority
+    pub modified: Amount,
+    /// Modified fees of in-mempool ancestors (including this one)
+    pub ancestor: Amount,
+    /// Modified fees of in-mempool descendants (including this one)
+    pub descendant: Amount,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TxInInfoSignTx {
     #[serde(rename = "txid")]
-    pub tx_id: String,
+    pub tx_id: Txid,
     pub vout: u32,
     #[serde(rename = "scriptPubKey")]
     pub script_pub_key_hex: String,
     #[serde(rename = "redeemScript")]
     pub redeem_script_hex: String,
     #[serde(rename = "amount")]
-    pub amount: f64,
+    pub amount: Amount,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct TxInInfoCreateTx {
     #[serde(rename = "txid")]
-    pub tx_id: String,
+    pub tx_id: Txid,
     pub vout: u32,
     #[serde(rename = "scriptPubKey")]
     pub script_pub_key_hex: String,
@@ -141,4 +623,53 @@ pub struct TxInInfoCreateTx {
 pub struct SignedRawTransaction {
     pub hex: String,
     pub complete: bool,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Options of "fundrawtransaction"
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FundRawTransactionOptions {
+    /// The address to receive the change
+    #[serde(rename = "changeAddress", skip_serializing_if = "Option::is_none")]
+    pub change_address: Option<String>,
+    /// The index of the change output
+    #[serde(rename = "changePosition", skip_serializing_if = "Option::is_none")]
+    pub change_position: Option<u32>,
+    /// Also select inputs which are watch only
+    #[serde(rename = "includeWatching", skip_serializing_if = "Option::is_none")]
+    pub include_watching: Option<bool>,
+    /// Lock selected unspent outputs
+    #[serde(rename = "lockUnspents", skip_serializing_if = "Option::is_none")]
+    pub lock_unspents: Option<bool>,
+    /// Set a specific fee rate
+    #[serde(rename = "feeRate", skip_serializing_if = "Option::is_none")]
+    pub fee_rate: Option<FeeRate>,
+    /// The outputs to subtract the fee from
+    #[serde(rename = "subtractFeeFromOutputs", skip_serializing_if = "Option::is_none")]
+    pub subtract_fee_from_outputs: Option<Vec<u32>>,
+    /// Mark this transaction as BIP125 replaceable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaceable: Option<bool>,
+    /// Confirmation target in blocks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conf_target: Option<u16>,
+    /// The fee estimate mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate_mode: Option<EstimateMode>,
+}
+
+/// Models the result of "fundrawtransaction"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FundRawTransactionResult {
+    /// The resulting raw transaction (hex-encoded string)
+    pub hex: String,
+    /// Fee the resulting transaction pays
+    pub fee: Amount,
+    /// The position of the added change output, or -1
+    pub changepos: i32,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
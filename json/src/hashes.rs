@@ -0,0 +1,126 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Typed double-SHA256 hashes.
+//!
+//! Each kind of hash is its own type so a txid can't be passed where a
+//! block hash is expected. They are (de)serialized as the hex strings
+//! bitcoind uses.
+
+use std::fmt::{self, Formatter};
+use std::str::FromStr;
+
+use bitcoin::util::hash::{HexError, Sha256dHash};
+use serde::{de, ser};
+
+macro_rules! hash_newtype {
+    ($name:ident, $expecting:expr) => {
+        impl $name {
+            /// Decodes a hash from the hex string bitcoind uses.
+            pub fn from_hex(s: &str) -> Result<$name, HexError> {
+                Sha256dHash::from_hex(s).map($name)
+            }
+
+            /// The underlying hash.
+            pub fn as_hash(&self) -> &Sha256dHash {
+                &self.0
+            }
+
+            /// Converts into the underlying hash.
+            pub fn into_hash(self) -> Sha256dHash {
+                self.0
+            }
+        }
+
+        impl From<Sha256dHash> for $name {
+            fn from(hash: Sha256dHash) -> $name {
+                $name(hash)
+            }
+        }
+
+        impl From<$name> for Sha256dHash {
+            fn from(hash: $name) -> Sha256dHash {
+                hash.0
+            }
+        }
+
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+                fmt::Display::fmt(&self.0, f)
+            }
+        }
+
+        impl FromStr for $name {
+            type Err = HexError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                $name::from_hex(s)
+            }
+        }
+
+        impl<'de> de::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: de::Deserializer<'de>,
+            {
+                struct Visitor;
+
+                impl<'de> de::Visitor<'de> for Visitor {
+                    type Value = $name;
+
+                    fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                        write!(fmt, $expecting)
+                    }
+
+                    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+                    where
+                        E: de::Error,
+                    {
+                        $name::from_hex(v)
+                            .map_err(|_e| de::Error::invalid_value(de::Unexpected::Str(v), &self))
+                    }
+                }
+
+                deserializer.deserialize_str(Visitor)
+            }
+        }
+
+        impl ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ser::Serializer,
+            {
+                serializer.serialize_str(&self.to_string())
+            }
+        }
+    };
+}
+
+/// The hash of a block header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BlockHash(Sha256dHash);
+
+hash_newtype!(BlockHash, "a hex-encoded block hash");
+
+/// The id of a transaction, the hash of its serialization without witness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Txid(Sha256dHash);
+
+hash_newtype!(Txid, "a hex-encoded txid");
+
+/// The witness id of a transaction, the hash of its full serialization.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Wtxid(Sha256dHash);
+
+hash_newtype!(Wtxid, "a hex-encoded wtxid");
+
+/// The root of the merkle tree of the transactions of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TxMerkleRoot(Sha256dHash);
+
+hash_newtype!(TxMerkleRoot, "a hex-encoded merkle root");
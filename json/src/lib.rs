@@ -9,10 +9,45 @@
 extern crate serde;
 #[macro_use]
 extern crate serde_derive;
-extern crate strason;
+extern crate serde_json;
 
 extern crate bitcoin;
 
+use std::collections::HashMap;
+
+use serde::de;
+
+#[macro_use]
+mod macros;
+
+pub mod amount;
 pub mod blockchain;
+pub mod hashes;
 pub mod mining;
 pub mod net;
+pub mod wallet;
+pub mod zmq;
+
+/// Fields of a response not modeled by its type, kept so that new fields
+/// added by Bitcoin Core aren't lost.
+pub type Extra = HashMap<String, serde_json::Value>;
+
+/// Deserializes "warnings", a string before 28.0 and a list of strings
+/// since.
+fn deserialize_warnings<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: de::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Warnings {
+        One(String),
+        Many(Vec<String>),
+    }
+
+    Ok(match de::Deserialize::deserialize(deserializer)? {
+        Warnings::One(ref warning) if warning.is_empty() => Vec::new(),
+        Warnings::One(warning) => vec![warning],
+        Warnings::Many(warnings) => warnings,
+    })
+}
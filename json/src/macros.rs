@@ -0,0 +1,84 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Macros shared by the result type modules.
+
+/// Defines an enum of the string values of an RPC field or argument.
+///
+/// Every enum gets an extra `Unknown(String)` variant holding values this
+/// crate doesn't know about, so a newer bitcoind never breaks
+/// deserialization. It is (de)serialized as the string, and implements
+/// `FromStr` and `Display` the same way.
+macro_rules! string_enum {
+    (
+        $(#[$attr:meta])*
+        pub enum $name:ident {
+            $(
+                $(#[$variant_attr:meta])*
+                $variant:ident => $s:tt,
+            )*
+        }
+    ) => {
+        $(#[$attr])*
+        #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+        pub enum $name {
+            $(
+                $(#[$variant_attr])*
+                $variant,
+            )*
+            /// A value not known to this crate
+            Unknown(String),
+        }
+
+        impl $name {
+            /// The string bitcoind uses for this value.
+            pub fn as_str(&self) -> &str {
+                match *self {
+                    $($name::$variant => $s,)*
+                    $name::Unknown(ref s) => s,
+                }
+            }
+        }
+
+        impl ::std::str::FromStr for $name {
+            type Err = ::std::string::ParseError;
+
+            fn from_str(s: &str) -> Result<Self, Self::Err> {
+                Ok(match s {
+                    $($s => $name::$variant,)*
+                    _ => $name::Unknown(s.to_owned()),
+                })
+            }
+        }
+
+        impl ::std::fmt::Display for $name {
+            fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl<'de> ::serde::de::Deserialize<'de> for $name {
+            fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+            where
+                D: ::serde::de::Deserializer<'de>,
+            {
+                let s = <String as ::serde::de::Deserialize>::deserialize(deserializer)?;
+                s.parse().map_err(::serde::de::Error::custom)
+            }
+        }
+
+        impl ::serde::ser::Serialize for $name {
+            fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+            where
+                S: ::serde::ser::Serializer,
+            {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+    };
+}
@@ -8,11 +8,16 @@
 
 //! Network related RPC result types.
 
+use std::collections::HashMap;
 use std::fmt::{self, Formatter};
+use std::net::SocketAddr;
 use std::str::FromStr;
+use std::time::Duration;
 
 use serde::{de, ser};
-use strason::Json;
+
+use amount::FeeRate;
+use Extra;
 
 /// The result of "getnetworkinfo"
 #[derive(Debug, Deserialize, Serialize)]
@@ -24,7 +29,7 @@ pub struct NetworkInfo {
     /// The protocol version
     pub protocolversion: i64,
     /// The services we offer to the network
-    pub localservices: Option<String>,
+    pub localservices: Option<ServiceFlags>,
     /// `true` if transaction relay is requested from peers
     pub localrelay: bool,
     /// The time offset
@@ -35,96 +40,35 @@ pub struct NetworkInfo {
     pub connections: Option<i64>,
     /// Information per network
     pub networks: Vec<Network>,
-    /// Minimum relay fee for transactions in BTC/kB
-    pub relayfee: Json,
-    /// Minimum fee increment for mempool limiting or BIP 125 replacement in
-    /// BTC/kB
-    pub incrementalfee: Json,
+    /// Minimum relay fee for transactions
+    pub relayfee: FeeRate,
+    /// Minimum fee increment for mempool limiting or BIP 125 replacement
+    pub incrementalfee: FeeRate,
     /// List of local addresses
     pub localaddresses: Vec<LocalAddress>,
     /// Any network and blockchain warnings
-    pub warnings: String,
-}
-
-/// Network name.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum NetworkName {
-    /// IPv4
-    Ipv4,
-    /// IPv6
-    Ipv6,
-    /// Onion
-    Onion,
-}
-
-impl FromStr for NetworkName {
-    type Err = ();
-
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "ipv4" => Ok(NetworkName::Ipv4),
-            "ipv6" => Ok(NetworkName::Ipv6),
-            "onion" => Ok(NetworkName::Onion),
-            _ => Err(()),
-        }
-    }
-}
-
-impl<'de> de::Deserialize<'de> for NetworkName {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: de::Deserializer<'de>,
-    {
-        struct Visitor;
-
-        impl<'de> de::Visitor<'de> for Visitor {
-            type Value = NetworkName;
-
-            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
-                write!(fmt, "network name")
-            }
-
-            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                NetworkName::from_str(v)
-                    .map_err(|_e| de::Error::custom("invalid string"))
-            }
-
-            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                NetworkName::from_str(v)
-                    .map_err(|_e| de::Error::custom("invalid string"))
-            }
-
-            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                NetworkName::from_str(&*v)
-                    .map_err(|_e| de::Error::custom("invalid string"))
-            }
-        }
-
-        deserializer.deserialize_str(Visitor)
-    }
+    #[serde(deserialize_with = "::deserialize_warnings")]
+    pub warnings: Vec<String>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-impl ser::Serialize for NetworkName {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: ser::Serializer,
-    {
-        let s = match *self {
-            NetworkName::Ipv4 => "ipv4",
-            NetworkName::Ipv6 => "ipv6",
-            NetworkName::Onion => "onion",
-        };
-
-        serializer.serialize_str(s)
+string_enum! {
+    /// Network name.
+    pub enum NetworkName {
+        /// IPv4
+        Ipv4 => "ipv4",
+        /// IPv6
+        Ipv6 => "ipv6",
+        /// Onion
+        Onion => "onion",
+        /// I2P (0.21.1+)
+        I2p => "i2p",
+        /// CJDNS (22.0+)
+        Cjdns => "cjdns",
+        /// Local, internal or otherwise not routable addresses (0.21+)
+        NotPubliclyRoutable => "not_publicly_routable",
     }
 }
 
@@ -141,6 +85,9 @@ pub struct Network {
     pub proxy: String,
     /// Whether randomized credentials are used
     pub proxy_randomize_credentials: bool,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -151,31 +98,44 @@ pub struct LocalAddress {
     pub port: u16,
     /// Relative score
     pub score: i64,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-/// Models the result of "getpeerinfo"
+/// Models an entry of the result of "getpeerinfo"
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct PeerInfo {
     /// Peer index
     pub id: u64,
-    /// The IP address and port of the peer
-    // TODO: use a type for addr
-    pub addr: String,
-    /// Bind address of the connection to the peer
-    // TODO: use a type for addrbind
-    pub addrbind: String,
-    /// Local address as reported by the peer
-    // TODO: use a type for addrlocal
-    pub addrlocal: String,
+    /// The address and port of the peer
+    pub addr: PeerAddress,
+    /// Bind address of the connection to the peer (0.16+)
+    pub addrbind: Option<PeerAddress>,
+    /// Local address as reported by the peer (only present if known)
+    pub addrlocal: Option<PeerAddress>,
+    /// The network of the peer (0.21+)
+    pub network: Option<NetworkName>,
+    /// The AS number used for bucketing the peer (only present with
+    /// `-asmap`)
+    pub mapped_as: Option<u32>,
     /// The services offered
-    // TODO: use a type for services
-    pub services: String,
-    /// Whether peer has asked us to relay transactions to it
-    pub relaytxes: bool,
+    pub services: ServiceFlags,
+    /// The names of the services offered (0.19+)
+    pub servicesnames: Option<Vec<String>>,
+    /// Whether peer has asked us to relay transactions to it (removed on
+    /// 27.0+ for block-relay-only peers)
+    pub relaytxes: Option<bool>,
     /// The time in seconds since epoch (Jan 1 1970 GMT) of the last send
     pub lastsend: u64,
     /// The time in seconds since epoch (Jan 1 1970 GMT) of the last receive
     pub lastrecv: u64,
+    /// The time in seconds since epoch (Jan 1 1970 GMT) of the last valid
+    /// transaction received from the peer (0.21+)
+    pub last_transaction: Option<u64>,
+    /// The time in seconds since epoch (Jan 1 1970 GMT) of the last block
+    /// received from the peer (0.21+)
+    pub last_block: Option<u64>,
     /// The total bytes sent
     pub bytessent: u64,
     /// The total bytes received
@@ -183,67 +143,121 @@ pub struct PeerInfo {
     /// The connection time in seconds since epoch (Jan 1 1970 GMT)
     pub conntime: u64,
     /// The time offset in seconds
-    pub timeoffset: u64,
-    /// ping time (if available)
-    pub pingtime: u64,
-    /// minimum observed ping time (if any at all)
-    pub minping: u64,
-    /// ping wait (if non-zero)
-    pub pingwait: u64,
+    pub timeoffset: i64,
+    /// Last ping time (only present once a ping was answered)
+    #[serde(default, with = "opt_duration_secs")]
+    pub pingtime: Option<Duration>,
+    /// Minimum observed ping time (only present once a ping was answered)
+    #[serde(default, with = "opt_duration_secs")]
+    pub minping: Option<Duration>,
+    /// Time waited for the outstanding ping (only present while waiting)
+    #[serde(default, with = "opt_duration_secs")]
+    pub pingwait: Option<Duration>,
     /// The peer version, such as 70001
-    pub version: u64,
+    pub version: u32,
     /// The string version
     pub subver: String,
     /// Inbound (true) or Outbound (false)
     pub inbound: bool,
+    /// Whether we selected the peer as a high-bandwidth compact block peer
+    /// (0.21+)
+    pub bip152_hb_to: Option<bool>,
+    /// Whether the peer selected us as a high-bandwidth compact block peer
+    /// (0.21+)
+    pub bip152_hb_from: Option<bool>,
     /// Whether connection was due to `addnode`/`-connect` or if it was an
-    /// automatic/inbound connection
-    pub addnode: bool,
-    /// The starting height (block) of the peer
-    pub startingheight: u64,
-    /// The ban score
-    pub banscore: i64,
-    /// The last header we have in common with this peer
-    pub synced_headers: u64,
-    /// The last block we have in common with this peer
-    pub synced_blocks: u64,
+    /// automatic/inbound connection (removed on 0.21+, see
+    /// `connection_type`)
+    pub addnode: Option<bool>,
+    /// The type of the connection (0.21+)
+    pub connection_type: Option<ConnectionType>,
+    /// The transport protocol (26.0+)
+    pub transport_protocol_type: Option<TransportProtocol>,
+    /// The session id of a v2 transport connection, or "" (26.0+)
+    pub session_id: Option<String>,
+    /// The starting height (block) of the peer (removed on 28.0+)
+    pub startingheight: Option<i64>,
+    /// The ban score (removed on 0.21+)
+    pub banscore: Option<i64>,
+    /// The last header we have in common with this peer, -1 if unknown
+    /// (missing if the node has no sync state for the peer)
+    pub synced_headers: Option<i64>,
+    /// The last block we have in common with this peer, -1 if unknown
+    /// (missing if the node has no sync state for the peer)
+    pub synced_blocks: Option<i64>,
     /// The heights of blocks we're currently asking from this peer
-    pub inflight: Vec<u64>,
-    /// Whether the peer is whitelisted
-    pub whitelisted: bool,
+    pub inflight: Option<Vec<u64>>,
+    /// Whether the peer is whitelisted (removed on 0.21+, see
+    /// `permissions`)
+    pub whitelisted: Option<bool>,
+    /// The permissions granted to the peer (0.19+)
+    pub permissions: Option<Vec<String>>,
+    /// The minimum fee rate of transactions the peer accepts (0.19+)
+    pub minfeefilter: Option<FeeRate>,
     /// The total bytes sent aggregated by message type
-    // TODO: use a type for bytessent_per_msg
-    pub bytessent_per_msg: Json,
+    pub bytessent_per_msg: HashMap<MessageType, u64>,
     /// The total bytes received aggregated by message type
-    // TODO: use a type for bytesrecv_per_msg
-    pub bytesrecv_per_msg: Json,
+    pub bytesrecv_per_msg: HashMap<MessageType, u64>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
 }
 
-/// "addnode" command.
-#[derive(Debug, Clone, Copy, Eq, PartialEq)]
-pub enum AddNode {
-    /// Add the node
-    Add,
-    /// Remove the node
-    Remove,
-    /// Try to connect once to the node
-    OneTry,
+/// The address of a peer, with its port
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PeerAddress {
+    /// An IPv4 or IPv6 address
+    Ip(SocketAddr),
+    /// A host name, e.g. an onion, I2P or CJDNS address
+    Host(String, u16),
 }
 
-impl FromStr for AddNode {
+impl PeerAddress {
+    /// The socket address, if this is an IP address.
+    pub fn socket_addr(&self) -> Option<SocketAddr> {
+        match *self {
+            PeerAddress::Ip(addr) => Some(addr),
+            PeerAddress::Host(..) => None,
+        }
+    }
+
+    /// The port.
+    pub fn port(&self) -> u16 {
+        match *self {
+            PeerAddress::Ip(ref addr) => addr.port(),
+            PeerAddress::Host(_, port) => port,
+        }
+    }
+}
+
+impl fmt::Display for PeerAddress {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            PeerAddress::Ip(ref addr) => fmt::Display::fmt(addr, f),
+            PeerAddress::Host(ref host, port) => write!(f, "{}:{}", host, port),
+        }
+    }
+}
+
+impl FromStr for PeerAddress {
     type Err = ();
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s {
-            "add" => Ok(AddNode::Add),
-            "remove" => Ok(AddNode::Remove),
-            "onetry" => Ok(AddNode::OneTry),
-            _ => Err(()),
+        if let Ok(addr) = SocketAddr::from_str(s) {
+            return Ok(PeerAddress::Ip(addr));
         }
+
+        let colon = s.rfind(':').ok_or(())?;
+        let host = &s[..colon];
+        let port = u16::from_str(&s[colon + 1..]).map_err(|_e| ())?;
+        if host.is_empty() || host.contains(':') {
+            return Err(());
+        }
+        Ok(PeerAddress::Host(host.to_owned(), port))
     }
 }
 
-impl<'de> de::Deserialize<'de> for AddNode {
+impl<'de> de::Deserialize<'de> for PeerAddress {
     fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
     where
         D: de::Deserializer<'de>,
@@ -251,34 +265,91 @@ impl<'de> de::Deserialize<'de> for AddNode {
         struct Visitor;
 
         impl<'de> de::Visitor<'de> for Visitor {
-            type Value = AddNode;
+            type Value = PeerAddress;
 
             fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
-                write!(fmt, "network name")
+                write!(fmt, "an address and port")
             }
 
             fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                AddNode::from_str(v)
-                    .map_err(|_e| de::Error::custom("invalid string"))
+                PeerAddress::from_str(v)
+                    .map_err(|_e| de::Error::invalid_value(de::Unexpected::Str(v), &self))
             }
+        }
 
-            fn visit_borrowed_str<E>(self, v: &'de str) -> Result<Self::Value, E>
-            where
-                E: de::Error,
-            {
-                AddNode::from_str(v)
-                    .map_err(|_e| de::Error::custom("invalid string"))
+        deserializer.deserialize_str(Visitor)
+    }
+}
+
+impl ser::Serialize for PeerAddress {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: ser::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+/// Service flags of a node, as defined in the P2P protocol
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct ServiceFlags(u64);
+
+impl ServiceFlags {
+    /// Serves the full block chain
+    pub const NETWORK: ServiceFlags = ServiceFlags(1 << 0);
+    /// Serves UTXO queries as defined in BIP64
+    pub const GETUTXO: ServiceFlags = ServiceFlags(1 << 1);
+    /// Serves bloom filtered connections as defined in BIP111
+    pub const BLOOM: ServiceFlags = ServiceFlags(1 << 2);
+    /// Serves witness data as defined in BIP144
+    pub const WITNESS: ServiceFlags = ServiceFlags(1 << 3);
+    /// Serves compact block filters as defined in BIP157
+    pub const COMPACT_FILTERS: ServiceFlags = ServiceFlags(1 << 6);
+    /// Serves the last 288 blocks as defined in BIP159
+    pub const NETWORK_LIMITED: ServiceFlags = ServiceFlags(1 << 10);
+    /// Supports the v2 transport protocol as defined in BIP324
+    pub const P2P_V2: ServiceFlags = ServiceFlags(1 << 11);
+
+    /// Creates flags from their bits.
+    pub fn from_bits(bits: u64) -> ServiceFlags {
+        ServiceFlags(bits)
+    }
+
+    /// The bits of the flags.
+    pub fn bits(&self) -> u64 {
+        self.0
+    }
+
+    /// Whether all of `flags` are set.
+    pub fn has(&self, flags: ServiceFlags) -> bool {
+        self.0 & flags.0 == flags.0
+    }
+}
+
+impl<'de> de::Deserialize<'de> for ServiceFlags {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: de::Deserializer<'de>,
+    {
+        struct Visitor;
+
+        impl<'de> de::Visitor<'de> for Visitor {
+            type Value = ServiceFlags;
+
+            fn expecting(&self, fmt: &mut Formatter) -> fmt::Result {
+                write!(fmt, "hex-encoded service flags")
             }
 
-            fn visit_string<E>(self, v: String) -> Result<Self::Value, E>
+            fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
             where
                 E: de::Error,
             {
-                AddNode::from_str(&*v)
-                    .map_err(|_e| de::Error::custom("invalid string"))
+                u64::from_str_radix(v, 16)
+                    .map(ServiceFlags)
+                    .map_err(|_e| de::Error::invalid_value(de::Unexpected::Str(v), &self))
             }
         }
 
@@ -286,17 +357,133 @@ impl<'de> de::Deserialize<'de> for AddNode {
     }
 }
 
-impl ser::Serialize for AddNode {
+impl ser::Serialize for ServiceFlags {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
         S: ser::Serializer,
     {
-        let s = match *self {
-            AddNode::Add => "add",
-            AddNode::Remove => "remove",
-            AddNode::OneTry => "onetry",
-        };
+        serializer.serialize_str(&format!("{:016x}", self.0))
+    }
+}
+
+string_enum! {
+    /// The type of a connection to a peer
+    pub enum ConnectionType {
+        /// Initiated by the peer
+        Inbound => "inbound",
+        /// Outbound relaying transactions, blocks and addresses
+        OutboundFullRelay => "outbound-full-relay",
+        /// Outbound relaying blocks only
+        BlockRelayOnly => "block-relay-only",
+        /// Added through `addnode` or `-connect`
+        Manual => "manual",
+        /// Short-lived, to test the reachability of an address
+        Feeler => "feeler",
+        /// Short-lived, to request addresses
+        AddrFetch => "addr-fetch",
+    }
+}
+
+string_enum! {
+    /// The type of a P2P message, as named in the protocol
+    pub enum MessageType {
+        Version => "version",
+        Verack => "verack",
+        Addr => "addr",
+        AddrV2 => "addrv2",
+        SendAddrV2 => "sendaddrv2",
+        Inv => "inv",
+        GetData => "getdata",
+        MerkleBlock => "merkleblock",
+        GetBlocks => "getblocks",
+        GetHeaders => "getheaders",
+        Tx => "tx",
+        Headers => "headers",
+        Block => "block",
+        GetAddr => "getaddr",
+        Mempool => "mempool",
+        Ping => "ping",
+        Pong => "pong",
+        NotFound => "notfound",
+        FilterLoad => "filterload",
+        FilterAdd => "filteradd",
+        FilterClear => "filterclear",
+        SendHeaders => "sendheaders",
+        FeeFilter => "feefilter",
+        SendCmpct => "sendcmpct",
+        CmpctBlock => "cmpctblock",
+        GetBlockTxn => "getblocktxn",
+        BlockTxn => "blocktxn",
+        GetCFilters => "getcfilters",
+        CFilter => "cfilter",
+        GetCFHeaders => "getcfheaders",
+        CFHeaders => "cfheaders",
+        GetCFCheckpt => "getcfcheckpt",
+        CFCheckpt => "cfcheckpt",
+        WtxidRelay => "wtxidrelay",
+        SendTxRcncl => "sendtxrcncl",
+        /// Rejection of a message (removed on 0.20+)
+        Reject => "reject",
+        /// All messages of types unknown to the node
+        Other => "*other*",
+    }
+}
+
+string_enum! {
+    /// The transport protocol of a connection
+    pub enum TransportProtocol {
+        /// Not known yet, for inbound connections
+        Detecting => "detecting",
+        /// The unencrypted protocol
+        V1 => "v1",
+        /// The encrypted protocol defined in BIP324
+        V2 => "v2",
+    }
+}
+
+/// (De)serializes an optional `Duration` as decimal seconds, for use with
+/// `#[serde(with = "...")]`.
+mod opt_duration_secs {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(v: &Option<Duration>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match *v {
+            Some(ref d) => {
+                let secs = d.as_secs() as f64 + f64::from(d.subsec_nanos()) / 1e9;
+                serializer.serialize_some(&secs)
+            }
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        let v: Option<f64> = Option::deserialize(deserializer)?;
+        Ok(v.map(|secs| {
+            // Times are printed with microsecond precision, which f64 can't
+            // always represent exactly; truncating would turn 2.141231 into
+            // 2s and 141_230_999ns.
+            let nanos = (secs.max(0.0) * 1e9).round() as u64;
+            Duration::new(nanos / 1_000_000_000, (nanos % 1_000_000_000) as u32)
+        }))
+    }
+}
 
-        serializer.serialize_str(s)
+string_enum! {
+    /// "addnode" command.
+    pub enum AddNode {
+        /// Add the node
+        Add => "add",
+        /// Remove the node
+        Remove => "remove",
+        /// Try to connect once to the node
+        OneTry => "onetry",
     }
 }
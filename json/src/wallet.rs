@@ -0,0 +1,86 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Wallet related RPC result types.
+
+use amount::{self, Amount, FeeRate};
+use hashes::Txid;
+use mining::EstimateMode;
+use Extra;
+
+/// Models an entry of the result of "listunspent"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ListUnspentResult {
+    /// The transaction id
+    pub txid: Txid,
+    /// The vout value
+    pub vout: u32,
+    /// The bitcoin address
+    pub address: Option<String>,
+    /// The associated label, or "" for the default label
+    pub label: Option<String>,
+    /// The script key
+    #[serde(rename = "scriptPubKey")]
+    pub script_pub_key: String,
+    /// The transaction output amount
+    pub amount: Amount,
+    /// The number of confirmations
+    pub confirmations: u64,
+    /// The redeem script if the script is P2SH
+    #[serde(rename = "redeemScript")]
+    pub redeem_script: Option<String>,
+    /// Whether we have the private keys to spend this output
+    pub spendable: bool,
+    /// Whether we know how to spend this output, ignoring the lack of keys
+    pub solvable: Option<bool>,
+    /// Whether this output is considered safe to spend
+    pub safe: Option<bool>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
+
+/// Options of "bumpfee" and "psbtbumpfee"
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct BumpFeeOptions {
+    /// Confirmation target in blocks
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub conf_target: Option<u16>,
+    /// Fee rate, sent in sat/vB (0.21+)
+    #[serde(
+        default,
+        with = "amount::opt_sat_per_vb",
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub fee_rate: Option<FeeRate>,
+    /// Whether the new transaction should still be BIP125 replaceable
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replaceable: Option<bool>,
+    /// The fee estimate mode
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub estimate_mode: Option<EstimateMode>,
+}
+
+/// Models the result of "bumpfee" and "psbtbumpfee"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct BumpFeeResult {
+    /// The id of the new transaction ("bumpfee" only)
+    pub txid: Option<Txid>,
+    /// The base64-encoded unsigned PSBT of the new transaction
+    /// ("psbtbumpfee" only)
+    pub psbt: Option<String>,
+    /// The fee of the replaced transaction
+    pub origfee: Amount,
+    /// The fee of the new transaction
+    pub fee: Amount,
+    /// Errors encountered during processing
+    pub errors: Vec<String>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
@@ -0,0 +1,26 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! ZMQ related RPC result types.
+
+use Extra;
+
+/// Models an entry of the result of "getzmqnotifications"
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ZmqNotification {
+    /// Type of notification, e.g. "pubrawblock"
+    #[serde(rename = "type")]
+    pub notification_type: String,
+    /// Address of the publisher
+    pub address: String,
+    /// Outbound message high water mark (only present on 0.17.1+)
+    pub hwm: Option<u64>,
+    /// Fields not modeled above
+    #[serde(flatten)]
+    pub extra: Extra,
+}
@@ -0,0 +1,422 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Deserializes the responses in `tests/fixtures/<version>/<rpc>.json`,
+//! in the format of several versions of Bitcoin Core.
+//!
+//! Blocks and transactions in the fixtures are consistent: hashes, sizes
+//! and merkle roots are computed from the serialized data, signatures are
+//! valid, and regtest and signet headers meet their target. See
+//! `tests/fixtures/README.md` for the node and command behind each file.
+
+extern crate bitcoin_rpc_json;
+extern crate serde;
+extern crate serde_json;
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::de::DeserializeOwned;
+
+use bitcoin_rpc_json::amount::{Amount, FeeRate};
+use bitcoin_rpc_json::blockchain::{
+    Bip9Status, BlockInfo, BlockRef, BlockchainInfo, DecodedTx, DeploymentInfo, DeploymentType,
+    FundRawTransactionResult, MempoolEntry, MempoolInfo, Network, SignedRawTransaction, TxOutInfo,
+};
+use bitcoin_rpc_json::mining::{EstimateMode, EstimateSmartFee};
+use bitcoin_rpc_json::net::{
+    ConnectionType, MessageType, NetworkInfo, NetworkName, PeerAddress, PeerInfo, ServiceFlags,
+    TransportProtocol,
+};
+use bitcoin_rpc_json::wallet::{BumpFeeResult, ListUnspentResult};
+use bitcoin_rpc_json::zmq::ZmqNotification;
+
+/// Parses the fixture of `rpc` of every version that has one, as
+/// `(version, result)` pairs ordered by version.
+fn parse<T: DeserializeOwned>(rpc: &str) -> Vec<(String, T)> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures");
+
+    let mut versions: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    versions.sort();
+
+    let mut results = Vec::new();
+    for version in versions {
+        let path = version.join(format!("{}.json", rpc));
+        if !path.exists() {
+            continue;
+        }
+
+        let name = version.file_name().unwrap().to_string_lossy().into_owned();
+        let json = fs::read_to_string(&path).unwrap();
+        let result = serde_json::from_str(&json)
+            .unwrap_or_else(|e| panic!("{} of {} doesn't deserialize: {}", rpc, name, e));
+        results.push((name, result));
+    }
+
+    assert!(!results.is_empty(), "no fixture for {}", rpc);
+    results
+}
+
+fn find<'a, T>(results: &'a [(String, T)], version: &str) -> &'a T {
+    &results.iter().find(|r| r.0 == version).unwrap().1
+}
+
+#[test]
+fn getblockchaininfo() {
+    let results = parse::<BlockchainInfo>("getblockchaininfo");
+
+    let v16 = find(&results, "v0.16");
+    assert_eq!(v16.chain, Network::Bitcoin);
+    assert!(v16.size_on_disk.is_none());
+    assert!(v16.warnings.is_empty());
+    let deployments = v16.deployments();
+    assert_eq!(deployments["bip65"].deployment_type, DeploymentType::Buried);
+    assert!(deployments["bip65"].active);
+    assert_eq!(deployments["segwit"].deployment_type, DeploymentType::Bip9);
+    assert!(deployments["segwit"].active);
+
+    let v19 = find(&results, "v0.19");
+    assert_eq!(v19.chain, Network::Testnet);
+    assert_eq!(v19.warnings.len(), 1);
+    let testdummy = v19.softforks["testdummy"].bip9.as_ref().unwrap();
+    assert_eq!(testdummy.status, Bip9Status::Started);
+    assert_eq!(testdummy.statistics.as_ref().unwrap().threshold, Some(1512));
+
+    let v21 = find(&results, "v0.21");
+    let taproot = v21.softforks["taproot"].bip9.as_ref().unwrap();
+    assert_eq!(taproot.start_time, -1);
+    assert_eq!(taproot.min_activation_height, Some(0));
+
+    let v28 = find(&results, "v28.0");
+    assert_eq!(v28.chain, Network::Signet);
+    assert!(v28.deployments().is_empty());
+    assert!(v28.extra.contains_key("time"));
+    assert!(v28.extra.contains_key("bits"));
+}
+
+#[test]
+fn getdeploymentinfo() {
+    let results = parse::<DeploymentInfo>("getdeploymentinfo");
+
+    let v28 = find(&results, "v28.0");
+    assert_eq!(v28.height, 215000);
+    assert!(v28.deployments["taproot"].active);
+    let testdummy = v28.deployments["testdummy"].bip9.as_ref().unwrap();
+    assert_eq!(testdummy.status_next, Some(Bip9Status::Started));
+    assert!(testdummy.extra.contains_key("signalling"));
+}
+
+#[test]
+fn getnetworkinfo() {
+    let results = parse::<NetworkInfo>("getnetworkinfo");
+
+    let v16 = find(&results, "v0.16");
+    assert_eq!(v16.relayfee, FeeRate::from_sat_per_vb(1));
+    assert!(v16.localservices.unwrap().has(ServiceFlags::WITNESS));
+
+    let v21 = find(&results, "v0.21");
+    assert!(v21.extra.contains_key("connections_in"));
+
+    let v28 = find(&results, "v28.0");
+    let names: Vec<_> = v28.networks.iter().map(|n| n.name.clone()).collect();
+    assert_eq!(
+        names,
+        vec![
+            NetworkName::Ipv4,
+            NetworkName::Ipv6,
+            NetworkName::Onion,
+            NetworkName::I2p,
+            NetworkName::Cjdns,
+        ]
+    );
+    assert!(v28.warnings.is_empty());
+}
+
+#[test]
+fn string_enums() {
+    let name: NetworkName = serde_json::from_str("\"yggdrasil\"").unwrap();
+    assert_eq!(name, NetworkName::Unknown("yggdrasil".to_owned()));
+    assert_eq!(serde_json::to_string(&name).unwrap(), "\"yggdrasil\"");
+
+    let chain: Network = serde_json::from_str("\"testnet5\"").unwrap();
+    assert_eq!(chain, Network::Unknown("testnet5".to_owned()));
+    assert!(chain.magic().is_none());
+    assert_eq!(Network::Bitcoin.to_string(), "main");
+    let status: Bip9Status = serde_json::from_str("\"signalling\"").unwrap();
+    assert_eq!(status, Bip9Status::Unknown("signalling".to_owned()));
+    assert_eq!(Bip9Status::LockedIn.as_str(), "locked_in");
+    assert_eq!(
+        "heretical".parse(),
+        Ok(DeploymentType::Unknown("heretical".to_owned()))
+    );
+
+    assert_eq!("ECONOMICAL".parse(), Ok(EstimateMode::Economical));
+    assert_eq!(EstimateMode::Conservative.to_string(), "CONSERVATIVE");
+    assert_eq!(
+        serde_json::to_string(&ConnectionType::OutboundFullRelay).unwrap(),
+        "\"outbound-full-relay\""
+    );
+
+    let per_msg: HashMap<MessageType, u64> =
+        serde_json::from_str(r#"{"tx": 1, "*other*": 2, "sendtxrcncl": 3, "newmsg": 4}"#).unwrap();
+    assert_eq!(per_msg[&MessageType::Tx], 1);
+    assert_eq!(per_msg[&MessageType::Other], 2);
+    assert_eq!(per_msg[&MessageType::SendTxRcncl], 3);
+    assert_eq!(per_msg[&MessageType::Unknown("newmsg".to_owned())], 4);
+}
+
+#[test]
+fn getpeerinfo() {
+    let results = parse::<Vec<PeerInfo>>("getpeerinfo");
+
+    let v16 = find(&results, "v0.16");
+    assert_eq!(v16[0].pingtime, Some(Duration::new(0, 87_532_000)));
+    assert_eq!(v16[0].minping, Some(Duration::new(0, 81_232_000)));
+    assert_eq!(v16[1].pingwait, Some(Duration::new(2, 141_231_000)));
+    assert_eq!(v16[0].bytesrecv_per_msg[&MessageType::Tx], 39364655);
+    assert_eq!(v16[0].synced_headers, Some(530000));
+    assert_eq!(v16[1].synced_blocks, Some(-1));
+    assert!(v16[1].addrlocal.is_none());
+    assert!(v16[1].pingtime.is_none());
+    assert_eq!(v16[1].addr.socket_addr().unwrap().port(), 48210);
+
+    let v19 = find(&results, "v0.19");
+    match v19[1].addr {
+        PeerAddress::Host(ref host, port) => {
+            assert!(host.ends_with(".onion"));
+            assert_eq!(port, 18333);
+        }
+        ref addr => panic!("unexpected address {}", addr),
+    }
+
+    let v21 = find(&results, "v0.21");
+    assert_eq!(v21[0].network, Some(NetworkName::Ipv4));
+    assert_eq!(v21[0].connection_type, Some(ConnectionType::Manual));
+    assert!(v21[0].banscore.is_none());
+
+    let v28 = find(&results, "v28.0");
+    assert!(v28[0].services.has(ServiceFlags::P2P_V2));
+    assert_eq!(v28[0].transport_protocol_type, Some(TransportProtocol::V2));
+    assert_eq!(v28[1].connection_type, Some(ConnectionType::BlockRelayOnly));
+    assert!(v28[1].pingwait.is_some());
+    assert!(v28[0].extra.contains_key("addr_processed"));
+    assert!(v28[0]
+        .bytessent_per_msg
+        .contains_key(&MessageType::WtxidRelay));
+
+    // Without sync state for the peer, its sync fields are left out.
+    let mut json = serde_json::to_value(&v28[0]).unwrap();
+    for field in &["synced_headers", "synced_blocks"] {
+        json.as_object_mut().unwrap().remove(*field);
+    }
+    let peer: PeerInfo = serde_json::from_value(json).unwrap();
+    assert!(peer.synced_headers.is_none());
+    assert!(peer.synced_blocks.is_none());
+}
+
+#[test]
+fn getpeerinfo_local() {
+    let results = parse::<Vec<PeerInfo>>("getpeerinfo-local");
+
+    let v21 = find(&results, "v0.21");
+    assert_eq!(v21[0].network, Some(NetworkName::NotPubliclyRoutable));
+    assert!(v21[0].addr.socket_addr().unwrap().ip().is_loopback());
+}
+
+#[test]
+fn getmempoolinfo() {
+    let results = parse::<MempoolInfo>("getmempoolinfo");
+
+    let v16 = find(&results, "v0.16");
+    assert!(v16.loaded.is_none());
+
+    let v28 = find(&results, "v28.0");
+    assert_eq!(v28.mempoolminfee, FeeRate::from_sat_per_kvb(1000));
+    assert_eq!(v28.extra["fullrbf"], serde_json::Value::Bool(true));
+
+    // Unknown fields survive a round trip.
+    let json = serde_json::to_value(v28).unwrap();
+    assert_eq!(json["unbroadcastcount"], serde_json::Value::from(0));
+}
+
+#[test]
+fn getmempoolentry() {
+    let results = parse::<MempoolEntry>("getmempoolentry");
+
+    let v16 = find(&results, "v0.16");
+    assert_eq!(v16.size, Some(225));
+    assert!(v16.vsize.is_none());
+    assert_eq!(v16.fee, Some(Amount::from_sat(4500)));
+    assert!(v16.fees.is_none());
+    assert!(v16.spentby.is_none());
+
+    let v19 = find(&results, "v0.19");
+    assert_eq!(v19.fee, Some(Amount::from_sat(2820)));
+
+    let v28 = find(&results, "v28.0");
+    assert!(v28.fee.is_none());
+    assert_eq!(v28.fees.as_ref().unwrap().ancestor, Amount::from_sat(4080));
+    assert_eq!(v28.depends.len(), 1);
+}
+
+#[test]
+fn estimatesmartfee() {
+    let results = parse::<EstimateSmartFee>("estimatesmartfee");
+
+    assert_eq!(
+        find(&results, "v0.16").feerate,
+        Some(FeeRate::from_sat_per_kvb(11237))
+    );
+    assert!(find(&results, "v0.19").feerate.is_none());
+}
+
+#[test]
+fn getblock() {
+    let results = parse::<BlockInfo>("getblock");
+
+    let v16 = find(&results, "v0.16");
+    assert_eq!(v16.height, 0);
+    assert!(v16.previousblockhash.is_none());
+    assert!(v16.n_tx.is_none());
+
+    let v19 = find(&results, "v0.19");
+    assert!(v19.nextblockhash.is_none());
+}
+
+#[test]
+fn getblock_verbosity_2() {
+    let results = parse::<BlockInfo<DecodedTx>>("getblock-2");
+
+    let coinbase = &find(&results, "v0.21").tx[0];
+    assert!(coinbase.vin[0].coinbase.is_some());
+    assert!(coinbase.vin[0].txid.is_none());
+    // Regtest halves the subsidy every 150 blocks.
+    assert_eq!(coinbase.vout[0].value, Amount::from_sat(1_250_000_000));
+    assert!(coinbase.vout[0].script_pub_key.addresses.is_some());
+}
+
+#[test]
+fn getblock_verbosity_3() {
+    let results = parse::<BlockInfo<DecodedTx>>("getblock-3");
+
+    let tx = &find(&results, "v28.0").tx[1];
+    assert_eq!(tx.fee, Some(Amount::from_sat(2040)));
+    let prevout = tx.vin[0].prevout.as_ref().unwrap();
+    assert_eq!(prevout.value, Amount::from_sat(50_000_000));
+    assert!(prevout.script_pub_key.address.is_some());
+    assert!(tx.vout[0].script_pub_key.extra.contains_key("desc"));
+}
+
+#[test]
+fn gettxout() {
+    let results = parse::<TxOutInfo>("gettxout");
+
+    let v16 = find(&results, "v0.16");
+    assert_eq!(v16.value, Amount::from_sat(1_500_000));
+    assert_eq!(v16.script_pub_key.req_sigs, Some(1));
+
+    let v21 = find(&results, "v0.21");
+    assert_eq!(v21.confirmations, 101);
+    assert!(v21.script_pub_key.addresses.is_some());
+
+    let v28 = find(&results, "v28.0");
+    assert_eq!(v28.confirmations, 0);
+    assert!(v28.script_pub_key.address.is_some());
+}
+
+#[test]
+fn waitfornewblock() {
+    let tips = parse::<BlockRef>("waitfornewblock");
+    let infos = parse::<BlockchainInfo>("getblockchaininfo");
+
+    assert_eq!(tips.len(), infos.len());
+    for ((version, tip), (_, info)) in tips.iter().zip(&infos) {
+        assert_eq!(tip.height, info.blocks, "{}", version);
+        assert_eq!(tip.hash, info.bestblockhash, "{}", version);
+    }
+}
+
+#[test]
+fn listunspent() {
+    let results = parse::<Vec<ListUnspentResult>>("listunspent");
+
+    let v16 = find(&results, "v0.16");
+    assert!(v16[0].label.is_none());
+    assert!(v16[0].extra.contains_key("account"));
+    assert!(v16[0].redeem_script.is_some());
+    assert!(!v16[0].extra.contains_key("desc"));
+
+    let v21 = find(&results, "v0.21");
+    assert_eq!(v21[0].amount, Amount::from_sat(5_000_000_000));
+    assert!(v21[0].redeem_script.is_none());
+    assert_eq!(v21[0].label, Some(String::new()));
+
+    let v28 = find(&results, "v28.0");
+    assert_eq!(v28[1].confirmations, 2);
+    assert_eq!(v28[1].safe, Some(true));
+    assert!(v28[0].extra.contains_key("parent_descs"));
+}
+
+#[test]
+fn fundrawtransaction() {
+    let results = parse::<FundRawTransactionResult>("fundrawtransaction");
+
+    assert_eq!(find(&results, "v0.16").fee, Amount::from_sat(2016));
+    assert_eq!(find(&results, "v28.0").changepos, 1);
+}
+
+#[test]
+fn signrawtransaction() {
+    let mut results = parse::<SignedRawTransaction>("signrawtransaction");
+    results.extend(parse::<SignedRawTransaction>(
+        "signrawtransactionwithwallet",
+    ));
+
+    let funded = parse::<FundRawTransactionResult>("fundrawtransaction");
+    for (version, signed) in &results {
+        assert!(signed.complete, "{}", version);
+        // Signing adds the segwit marker and flag after the version.
+        assert_eq!(&signed.hex[8..12], "0001", "{}", version);
+        assert_eq!(
+            &signed.hex[..8],
+            &find(&funded, version).hex[..8],
+            "{}",
+            version
+        );
+    }
+}
+
+#[test]
+fn bumpfee() {
+    let results = parse::<BumpFeeResult>("bumpfee");
+
+    for (version, bumped) in &results {
+        assert!(bumped.fee > bumped.origfee, "{}", version);
+        assert!(bumped.errors.is_empty(), "{}", version);
+        assert!(bumped.psbt.is_none(), "{}", version);
+    }
+    assert_eq!(find(&results, "v0.16").origfee, Amount::from_sat(2016));
+}
+
+#[test]
+fn getzmqnotifications() {
+    let results = parse::<Vec<ZmqNotification>>("getzmqnotifications");
+
+    let v19 = find(&results, "v0.19");
+    assert_eq!(v19[0].notification_type, "pubhashblock");
+    assert_eq!(v19[0].hwm, Some(1000));
+
+    let v28 = find(&results, "v28.0");
+    assert_eq!(v28[2].notification_type, "pubsequence");
+    assert_eq!(v28[2].address, "tcp://127.0.0.1:28334");
+}
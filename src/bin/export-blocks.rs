@@ -0,0 +1,53 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Exports a height range of blocks from a Bitcoin Core node to
+//! `blk*.dat`-compatible files.
+extern crate bitcoin_rpc;
+#[macro_use]
+extern crate failure;
+
+use bitcoin_rpc::blkfile::{self, BlockFileWriter};
+use bitcoin_rpc::BitcoinRpc;
+
+type Result<T> = std::result::Result<T, failure::Error>;
+
+const USAGE: &str = "Usage: <rpc_url> <start_height> <end_height> <out_dir> [username] [password]";
+
+fn main() -> Result<()> {
+    let mut args = std::env::args();
+
+    let _exe_name = args.next().unwrap();
+
+    let url = args.next().ok_or_else(|| format_err!("{}", USAGE))?;
+    let start: u64 = args
+        .next()
+        .ok_or_else(|| format_err!("{}", USAGE))?
+        .parse()?;
+    let end: u64 = args
+        .next()
+        .ok_or_else(|| format_err!("{}", USAGE))?
+        .parse()?;
+    if start > end {
+        bail!("start height {} is above end height {}", start, end);
+    }
+    let out_dir = args.next().ok_or_else(|| format_err!("{}", USAGE))?;
+    let user = args.next();
+    let pass = args.next();
+
+    let rpc = BitcoinRpc::new(url, user, pass);
+
+    let network = rpc.network()?;
+
+    let mut writer = BlockFileWriter::new(&out_dir, network, blkfile::MAX_BLOCKFILE_SIZE)?;
+    blkfile::export_blocks(&rpc, start, end, &mut writer)?;
+
+    println!("exported blocks {} to {} to {}", start, end, out_dir);
+
+    Ok(())
+}
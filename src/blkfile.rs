@@ -0,0 +1,400 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reading and writing blocks in Bitcoin Core's `blk*.dat` file format.
+//!
+//! Every block is stored as the network magic and the length of the block,
+//! both as little endian 32-bit integers, followed by the serialized block.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::{Path, PathBuf};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::network::serialize::{self, deserialize, serialize};
+
+use blockchain::Network;
+use {u32_from_le, BitcoinRpc};
+
+/// Maximum size of a `blk*.dat` file used by Bitcoin Core.
+pub const MAX_BLOCKFILE_SIZE: u64 = 0x800_0000;
+
+/// Maximum size of a serialized block, witness included.
+const MAX_BLOCK_SERIALIZED_SIZE: u32 = 4_000_000;
+
+/// Writes blocks to `blk00000.dat`, `blk00001.dat`, ... in a directory,
+/// starting a new file when the size limit would be exceeded. Existing
+/// files are never overwritten: writing to one fails instead.
+pub struct BlockFileWriter {
+    dir: PathBuf,
+    magic: u32,
+    max_file_size: u64,
+    file_index: u32,
+    file_size: u64,
+    file: Option<BufWriter<File>>,
+}
+
+impl BlockFileWriter {
+    /// Creates a writer for blocks of `network` in `dir`, creating the
+    /// directory if needed.
+    pub fn new<P: AsRef<Path>>(dir: P, network: Network, max_file_size: u64) -> BlkResult<Self> {
+        let magic = magic(network)?;
+        fs::create_dir_all(dir.as_ref())?;
+
+        Ok(BlockFileWriter {
+            dir: dir.as_ref().to_owned(),
+            magic: magic,
+            max_file_size: max_file_size,
+            file_index: 0,
+            file_size: 0,
+            file: None,
+        })
+    }
+
+    /// Appends `block` to the current file.
+    pub fn write_block(&mut self, block: &Block) -> BlkResult<()> {
+        let bytes = serialize(block)?;
+        let record_size = 8 + bytes.len() as u64;
+
+        if self.file.is_some() && self.file_size + record_size > self.max_file_size {
+            self.flush()?;
+            self.file = None;
+            self.file_index += 1;
+            self.file_size = 0;
+        }
+
+        if self.file.is_none() {
+            let path = self.dir.join(block_file_name(self.file_index));
+            let file = OpenOptions::new().write(true).create_new(true).open(path)?;
+            self.file = Some(BufWriter::new(file));
+        }
+
+        let file = self.file.as_mut().expect("file was just opened");
+        file.write_all(&u32_to_le(self.magic))?;
+        file.write_all(&u32_to_le(bytes.len() as u32))?;
+        file.write_all(&bytes)?;
+        self.file_size += record_size;
+
+        Ok(())
+    }
+
+    /// Flushes the current file.
+    pub fn flush(&mut self) -> BlkResult<()> {
+        if let Some(ref mut file) = self.file {
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Fetches the blocks from `start` to `end` (inclusive) through `get` and
+/// writes them with `writer`.
+pub fn export_blocks(
+    rpc: &BitcoinRpc,
+    start: u64,
+    end: u64,
+    writer: &mut BlockFileWriter,
+) -> BlkResult<()> {
+    for height in start..=end {
+        let hash = rpc.get_blockhash(height)?;
+        let block: Block = rpc.get(&hash)?;
+        writer.write_block(&block)?;
+    }
+
+    writer.flush()
+}
+
+/// Reads the blocks of a `blk*.dat` file.
+pub struct BlockFileReader<R> {
+    reader: R,
+    magic: u32,
+}
+
+impl BlockFileReader<BufReader<File>> {
+    /// Opens the file at `path` holding blocks of `network`.
+    pub fn open<P: AsRef<Path>>(path: P, network: Network) -> BlkResult<Self> {
+        BlockFileReader::new(BufReader::new(File::open(path)?), network)
+    }
+}
+
+impl<R: Read> BlockFileReader<R> {
+    /// Creates a reader of blocks of `network` from `reader`.
+    pub fn new(reader: R, network: Network) -> BlkResult<Self> {
+        Ok(BlockFileReader {
+            reader: reader,
+            magic: magic(network)?,
+        })
+    }
+
+    /// Reads the next block, or returns `None` at the end of the file.
+    pub fn read_block(&mut self) -> BlkResult<Option<Block>> {
+        let mut header = [0u8; 8];
+        if !read_exact_or_eof(&mut self.reader, &mut header)? {
+            return Ok(None);
+        }
+
+        let magic = u32_from_le(&header[..4]);
+        // Bitcoin Core preallocates files, so they may end with zeros.
+        if magic == 0 {
+            return Ok(None);
+        }
+        if magic != self.magic {
+            return Err(Error::BadMagic { magic: magic });
+        }
+
+        // Checked before allocating, a corrupt length could be up to 4 GiB.
+        let size = u32_from_le(&header[4..]);
+        if size > MAX_BLOCK_SERIALIZED_SIZE {
+            return Err(Error::BlockTooLarge { size: size });
+        }
+
+        let mut bytes = vec![0u8; size as usize];
+        self.reader.read_exact(&mut bytes)?;
+
+        Ok(Some(deserialize(&bytes)?))
+    }
+}
+
+impl<R: Read> Iterator for BlockFileReader<R> {
+    type Item = BlkResult<Block>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_block() {
+            Ok(Some(block)) => Some(Ok(block)),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// Returns the paths of the `blk*.dat` files in `dir`, in order.
+pub fn block_files<P: AsRef<Path>>(dir: P) -> BlkResult<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for entry in fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_block_file = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .map_or(false, |name| {
+                name.starts_with("blk") && name.ends_with(".dat")
+            });
+
+        if is_block_file {
+            files.push(path);
+        }
+    }
+    files.sort();
+
+    Ok(files)
+}
+
+fn block_file_name(index: u32) -> String {
+    format!("blk{:05}.dat", index)
+}
+
+fn magic(network: Network) -> BlkResult<u32> {
+    match network.magic() {
+        Some(magic) => Ok(magic),
+        None => Err(Error::UnknownNetwork { network: network }),
+    }
+}
+
+/// Like `read_exact`, but returns `false` on a clean end of file.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<bool> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(ref e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(true)
+}
+
+fn u32_to_le(v: u32) -> [u8; 4] {
+    [v as u8, (v >> 8) as u8, (v >> 16) as u8, (v >> 24) as u8]
+}
+
+pub type BlkResult<T> = Result<T, Error>;
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Rpc { err: e }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io { err: e }
+    }
+}
+
+impl From<serialize::Error> for Error {
+    fn from(e: serialize::Error) -> Error {
+        Error::Encoding { err: e }
+    }
+}
+
+/// The block file error type
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Fetching a block failed.
+    #[fail(display = "failed to fetch block")]
+    Rpc {
+        #[cause]
+        err: ::Error,
+    },
+    /// Reading or writing a file failed.
+    #[fail(display = "failed to access block file")]
+    Io {
+        #[cause]
+        err: io::Error,
+    },
+    /// A block could not be serialized or deserialized.
+    #[fail(display = "invalid block encoding")]
+    Encoding {
+        #[cause]
+        err: serialize::Error,
+    },
+    /// A record does not start with the network magic.
+    #[fail(display = "unexpected network magic {:#010x}", magic)]
+    BadMagic { magic: u32 },
+    /// A record is longer than any valid block.
+    #[fail(display = "block record of {} bytes is too large", size)]
+    BlockTooLarge { size: u32 },
+    /// The magic of the network is not known to this crate.
+    #[fail(display = "no network magic known for {}", network)]
+    UnknownNetwork { network: Network },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::io::Cursor;
+    use std::process;
+
+    use bitcoin::blockdata::transaction::OutPoint;
+    use bitcoin::util::hash::Sha256dHash;
+
+    use test_utils;
+
+    use super::*;
+
+    /// A block with a transaction paying `outputs` outputs, 132 bytes plus
+    /// 10 bytes per output.
+    fn block(nonce: u32, outputs: usize) -> Block {
+        let script = test_utils::op_true();
+        let spent = OutPoint {
+            txid: Sha256dHash::from_data(&[]),
+            vout: nonce,
+        };
+        let tx = test_utils::tx(&[spent], vec![], &vec![(&script, 5_000_000_000); outputs]);
+
+        Block {
+            header: test_utils::header(Sha256dHash::from_data(&[]), nonce),
+            txdata: vec![tx],
+        }
+    }
+
+    fn record(magic: u32, size: u32) -> Vec<u8> {
+        let mut bytes = u32_to_le(magic).to_vec();
+        bytes.extend_from_slice(&u32_to_le(size));
+        bytes
+    }
+
+    #[test]
+    fn write_read_round_trip() {
+        let dir = env::temp_dir().join(format!("bitcoin-rpc-blkfile-{}", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+
+        let blocks: Vec<Block> = (0..5)
+            .map(|nonce| block(nonce, nonce as usize * 10))
+            .collect();
+        // Room for the first two blocks only, so the rest is split.
+        let max_file_size = 2 * 8
+            + serialize(&blocks[0]).unwrap().len() as u64
+            + serialize(&blocks[1]).unwrap().len() as u64;
+
+        let mut writer = BlockFileWriter::new(&dir, Network::Regtest, max_file_size).unwrap();
+        for block in &blocks {
+            writer.write_block(block).unwrap();
+        }
+        writer.flush().unwrap();
+
+        let files = block_files(&dir).unwrap();
+        let names: Vec<_> = files
+            .iter()
+            .map(|p| p.file_name().unwrap().to_str().unwrap().to_owned())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "blk00000.dat",
+                "blk00001.dat",
+                "blk00002.dat",
+                "blk00003.dat"
+            ]
+        );
+
+        let mut read = Vec::new();
+        for file in &files {
+            for block in BlockFileReader::open(file, Network::Regtest).unwrap() {
+                read.push(block.unwrap());
+            }
+        }
+        assert_eq!(read, blocks);
+
+        match BlockFileReader::open(&files[0], Network::Bitcoin)
+            .unwrap()
+            .read_block()
+        {
+            Err(Error::BadMagic { magic }) => assert_eq!(magic, 0xdab5_bffa),
+            other => panic!("unexpected result {:?}", other),
+        }
+
+        // The files are left as they are by a second export.
+        let mut writer = BlockFileWriter::new(&dir, Network::Regtest, max_file_size).unwrap();
+        match writer.write_block(&blocks[0]) {
+            Err(Error::Io { ref err }) => assert_eq!(err.kind(), io::ErrorKind::AlreadyExists),
+            other => panic!("unexpected result {:?}", other),
+        }
+        assert_eq!(block_files(&dir).unwrap(), files);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn preallocated_end() {
+        // Bitcoin Core zero-fills the space preallocated after the last
+        // record.
+        let block_bytes = serialize(&block(0, 0)).unwrap();
+        let mut bytes = record(0xd9b4_bef9, block_bytes.len() as u32);
+        bytes.extend(block_bytes);
+        bytes.extend(vec![0u8; 16]);
+
+        let mut reader = BlockFileReader::new(Cursor::new(bytes), Network::Bitcoin).unwrap();
+        assert_eq!(reader.read_block().unwrap(), Some(block(0, 0)));
+        assert!(reader.read_block().unwrap().is_none());
+    }
+
+    #[test]
+    fn record_too_large() {
+        let bytes = record(0xd9b4_bef9, MAX_BLOCK_SERIALIZED_SIZE + 1);
+
+        let mut reader = BlockFileReader::new(Cursor::new(bytes), Network::Bitcoin).unwrap();
+        match reader.read_block() {
+            Err(Error::BlockTooLarge { size }) => assert_eq!(size, MAX_BLOCK_SERIALIZED_SIZE + 1),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,313 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Querying many items at once.
+//!
+//! Both `get_many` and `get_many_iter` keep up to `concurrency` requests in
+//! flight, each thread taking the next id as soon as its previous request
+//! completes. Every id is queried with requests of its own; batching several
+//! ids into one JSON-RPC call is not supported.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::thread;
+
+use crossbeam;
+
+use {BitcoinRpc, Error, Id, RpcResult};
+
+/// Number of ids queued per worker of `GetMany`.
+const QUEUED_PER_WORKER: usize = 2;
+
+/// Queries the items with `ids` from `concurrency` threads and returns them
+/// in the order of `ids`. Stops taking ids after an error, and fails with
+/// the error of the lowest failed id.
+pub fn get_many<T, I>(rpc: &BitcoinRpc, ids: &[I], concurrency: usize) -> RpcResult<Vec<T>>
+where
+    T: Send,
+    I: Id<T> + Sync,
+{
+    let next = AtomicUsize::new(0);
+    let failed = AtomicBool::new(false);
+    let threads = concurrency.max(1).min(ids.len());
+
+    let mut results: Vec<Option<T>> = (0..ids.len()).map(|_| None).collect();
+    let mut error: Option<(usize, Error)> = None;
+
+    crossbeam::scope(|scope| {
+        let handles: Vec<_> = (0..threads)
+            .map(|_| {
+                let next = &next;
+                let failed = &failed;
+                scope.spawn(move || {
+                    let mut fetched = Vec::new();
+                    // Stop taking ids once any thread failed.
+                    while !failed.load(Ordering::Relaxed) {
+                        let i = next.fetch_add(1, Ordering::Relaxed);
+                        if i >= ids.len() {
+                            break;
+                        }
+                        match ids[i].query(rpc) {
+                            Ok(item) => fetched.push((i, item)),
+                            Err(e) => {
+                                failed.store(true, Ordering::Relaxed);
+                                return Err((i, e));
+                            }
+                        }
+                    }
+                    Ok(fetched)
+                })
+            })
+            .collect();
+
+        for handle in handles {
+            match handle.join() {
+                Ok(fetched) => {
+                    for (i, item) in fetched {
+                        results[i] = Some(item);
+                    }
+                }
+                Err((i, e)) => {
+                    if error.as_ref().map_or(true, |&(first, _)| i < first) {
+                        error = Some((i, e));
+                    }
+                }
+            }
+        }
+    });
+
+    if let Some((_, e)) = error {
+        return Err(e);
+    }
+    // Without errors, the threads took every id.
+    Ok(results.into_iter().map(|item| item.unwrap()).collect())
+}
+
+/// Queries the items with `ids` from `concurrency` worker threads, and
+/// returns them as they arrive, in the order of `ids`.
+///
+/// Ids are taken from `ids` only as items are consumed, so at most
+/// `concurrency * 2` items are held in memory. An id that fails yields its
+/// error, and the iteration goes on with the next id. Ids left to a worker
+/// that panicked yield `Error::WorkerStopped`.
+pub fn get_many_iter<T, I, It>(
+    rpc: Arc<BitcoinRpc>,
+    ids: It,
+    concurrency: usize,
+) -> GetMany<T, I, It::IntoIter>
+where
+    T: Send + 'static,
+    I: Id<T> + Send + 'static,
+    It: IntoIterator<Item = I>,
+{
+    let workers = (0..concurrency.max(1))
+        .map(|_| {
+            let rpc = Arc::clone(&rpc);
+            let (id_tx, id_rx) = mpsc::channel::<I>();
+            let (result_tx, result_rx) = mpsc::channel();
+            thread::spawn(move || {
+                for id in id_rx {
+                    // The iterator was dropped.
+                    if result_tx.send(id.query(&rpc)).is_err() {
+                        break;
+                    }
+                }
+            });
+            Worker {
+                ids: id_tx,
+                results: result_rx,
+            }
+        })
+        .collect();
+
+    GetMany {
+        ids: ids.into_iter(),
+        workers: workers,
+        queued: VecDeque::new(),
+    }
+}
+
+/// Iterator over items queried by worker threads, returned by
+/// `get_many_iter`.
+///
+/// The workers stop when the iterator is dropped, after their current
+/// request.
+pub struct GetMany<T, I, It> {
+    ids: It,
+    workers: Vec<Worker<T, I>>,
+    /// The workers the queued ids were sent to, in the order of the ids
+    queued: VecDeque<usize>,
+}
+
+struct Worker<T, I> {
+    ids: Sender<I>,
+    results: Receiver<RpcResult<T>>,
+}
+
+impl<T, I, It> GetMany<T, I, It>
+where
+    It: Iterator<Item = I>,
+{
+    /// Sends ids to the workers until every worker has its share queued.
+    fn fill(&mut self) {
+        let workers = self.workers.len();
+        while self.queued.len() < workers * QUEUED_PER_WORKER {
+            let id = match self.ids.next() {
+                Some(id) => id,
+                None => break,
+            };
+            // Round-robin keeps results in order, as each worker handles
+            // its ids in order.
+            let worker = self.queued.back().map_or(0, |&w| (w + 1) % workers);
+            // If the worker stopped, its results channel is closed too, and
+            // `next` yields an error for the id.
+            let _ = self.workers[worker].ids.send(id);
+            self.queued.push_back(worker);
+        }
+    }
+}
+
+impl<T, I, It> Iterator for GetMany<T, I, It>
+where
+    It: Iterator<Item = I>,
+{
+    type Item = RpcResult<T>;
+
+    fn next(&mut self) -> Option<RpcResult<T>> {
+        self.fill();
+        let worker = self.queued.pop_front()?;
+        let item = self.workers[worker]
+            .results
+            .recv()
+            .unwrap_or(Err(Error::WorkerStopped));
+        self.fill();
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use blockchain::Network;
+    use Querable;
+
+    use super::*;
+
+    /// An id answered without a node: `n` after `delay_ms`, or an error
+    /// naming `n` if `fail` is set.
+    struct FakeId {
+        n: usize,
+        delay_ms: u64,
+        fail: bool,
+        panic: bool,
+    }
+
+    #[derive(Debug, PartialEq)]
+    struct Item(usize);
+
+    impl Querable<FakeId> for Item {
+        fn query(_rpc: &BitcoinRpc, id: &FakeId) -> RpcResult<Self> {
+            thread::sleep(Duration::from_millis(id.delay_ms));
+            if id.panic {
+                panic!("fake query of {} panicked", id.n);
+            }
+            if id.fail {
+                return Err(Error::WrongNetwork {
+                    address: id.n.to_string(),
+                    network: Network::Regtest,
+                });
+            }
+            Ok(Item(id.n))
+        }
+    }
+
+    /// Ids `0..count`, later ones answered faster so that they complete
+    /// out of order.
+    fn ids(count: usize, failing: &[usize]) -> Vec<FakeId> {
+        (0..count)
+            .map(|n| FakeId {
+                n: n,
+                delay_ms: ((count - n) * 3) as u64,
+                fail: failing.contains(&n),
+                panic: false,
+            })
+            .collect()
+    }
+
+    fn failed_id<T>(result: RpcResult<T>) -> usize {
+        match result {
+            Err(Error::WrongNetwork { address, .. }) => address.parse().unwrap(),
+            _ => panic!("expected a fake error"),
+        }
+    }
+
+    fn rpc() -> BitcoinRpc {
+        BitcoinRpc::new("http://127.0.0.1:1".to_owned(), None, None)
+    }
+
+    #[test]
+    fn get_many_keeps_order() {
+        let rpc = rpc();
+        for &concurrency in &[0, 1, 3, 8, 32] {
+            let items: Vec<Item> = get_many(&rpc, &ids(20, &[]), concurrency).unwrap();
+            let expected: Vec<Item> = (0..20).map(Item).collect();
+            assert_eq!(items, expected, "concurrency {}", concurrency);
+        }
+
+        let empty: Vec<Item> = get_many(&rpc, &ids(0, &[]), 4).unwrap();
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn get_many_lowest_error_wins() {
+        let rpc = rpc();
+        // The later failure completes first.
+        let result: RpcResult<Vec<Item>> = get_many(&rpc, &ids(10, &[3, 7]), 8);
+        assert_eq!(failed_id(result), 3);
+
+        let result: RpcResult<Vec<Item>> = get_many(&rpc, &ids(10, &[9]), 1);
+        assert_eq!(failed_id(result), 9);
+    }
+
+    #[test]
+    fn get_many_iter_keeps_order() {
+        let rpc = Arc::new(rpc());
+        let results: Vec<RpcResult<Item>> = get_many_iter(rpc, ids(20, &[4, 11]), 3).collect();
+
+        assert_eq!(results.len(), 20);
+        for (n, result) in results.into_iter().enumerate() {
+            if n == 4 || n == 11 {
+                assert_eq!(failed_id(result), n);
+            } else {
+                assert_eq!(result.unwrap(), Item(n));
+            }
+        }
+    }
+
+    #[test]
+    fn get_many_iter_worker_panic() {
+        let rpc = Arc::new(rpc());
+        let mut ids = ids(4, &[]);
+        ids[1].panic = true;
+
+        // Worker 0 gets ids 0 and 2, worker 1 ids 1 and 3.
+        let results: Vec<RpcResult<Item>> = get_many_iter(rpc, ids, 2).collect();
+        assert_eq!(results.len(), 4);
+        assert_eq!(*results[0].as_ref().unwrap(), Item(0));
+        assert_eq!(*results[2].as_ref().unwrap(), Item(2));
+        for &n in &[1, 3] {
+            match results[n] {
+                Err(Error::WorkerStopped) => {}
+                _ => panic!("expected a stopped worker for {}", n),
+            }
+        }
+    }
+}
@@ -0,0 +1,382 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Client-side coin selection over the result of "listunspent".
+
+use amount::{Amount, FeeRate};
+use blockchain::TxInInfoCreateTx;
+use wallet::ListUnspentResult;
+
+/// Maximum number of branch and bound iterations.
+const BNB_MAX_TRIES: usize = 100_000;
+
+/// Parameters of a coin selection.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CoinSelectionParams {
+    /// Sum of the recipient outputs
+    pub target: Amount,
+    /// Virtual size of the transaction without inputs or change, i.e. the
+    /// overhead plus the recipient outputs
+    pub base_vsize: u64,
+    /// Fee rate of the transaction
+    pub feerate: FeeRate,
+    /// Fee rate expected to spend the inputs later, used for the waste
+    /// metric
+    pub long_term_feerate: FeeRate,
+    /// Virtual size of the change output
+    pub change_output_vsize: u64,
+    /// Virtual size of the input spending the change output later
+    pub change_spend_vsize: u64,
+    /// Smallest change output to create
+    pub min_change: Amount,
+}
+
+impl CoinSelectionParams {
+    /// Creates parameters for paying `target` to a single P2WPKH output with
+    /// P2WPKH change.
+    pub fn new(target: Amount, feerate: FeeRate) -> Self {
+        CoinSelectionParams {
+            target: target,
+            base_vsize: 11 + 31,
+            feerate: feerate,
+            long_term_feerate: FeeRate::from_sat_per_vb(10),
+            change_output_vsize: 31,
+            change_spend_vsize: 68,
+            min_change: Amount::from_sat(546),
+        }
+    }
+
+    fn cost_of_change(&self) -> u64 {
+        sat(self.feerate.fee(self.change_output_vsize))
+            + sat(self.long_term_feerate.fee(self.change_spend_vsize))
+    }
+}
+
+/// The result of a coin selection.
+#[derive(Debug, Clone)]
+pub struct Selection {
+    /// The selected unspent outputs
+    pub inputs: Vec<ListUnspentResult>,
+    /// Value of the change output, zero if there is none
+    pub change: Amount,
+    /// Fee paid by the transaction
+    pub fee: Amount,
+    /// Waste of the selection, compared to spending the inputs at the long
+    /// term fee rate and without change
+    pub waste: Amount,
+}
+
+impl Selection {
+    /// The selected inputs as taken by `create_raw_transaction`.
+    pub fn create_tx_inputs(&self) -> Vec<TxInInfoCreateTx> {
+        self.inputs
+            .iter()
+            .map(|u| TxInInfoCreateTx {
+                tx_id: u.txid,
+                vout: u.vout,
+                script_pub_key_hex: u.script_pub_key.clone(),
+            })
+            .collect()
+    }
+}
+
+struct Candidate<'a> {
+    utxo: &'a ListUnspentResult,
+    effective_value: u64,
+    fee: u64,
+    waste: i64,
+}
+
+/// Selects inputs from `utxos` paying for `params.target` at
+/// `params.feerate`.
+///
+/// Branch and bound is tried first to find a changeless selection with the
+/// least waste; if there is none, inputs are taken largest first. Returns
+/// `None` if the funds are insufficient.
+pub fn select_coins(
+    utxos: &[ListUnspentResult],
+    params: &CoinSelectionParams,
+) -> Option<Selection> {
+    let mut candidates: Vec<Candidate> = utxos
+        .iter()
+        .filter_map(|utxo| {
+            let vsize = estimate_input_vsize(&utxo.script_pub_key);
+            let fee = sat(params.feerate.fee(vsize));
+            let value = sat(utxo.amount);
+            if value <= fee {
+                return None;
+            }
+
+            Some(Candidate {
+                utxo: utxo,
+                effective_value: value - fee,
+                fee: fee,
+                waste: fee as i64 - params.long_term_feerate.fee(vsize).as_sat(),
+            })
+        })
+        .collect();
+    candidates.sort_by(|a, b| b.effective_value.cmp(&a.effective_value));
+
+    let base_fee = sat(params.feerate.fee(params.base_vsize));
+    let target = sat(params.target) + base_fee;
+
+    if let Some((selected, waste)) = branch_and_bound(&candidates, target, params.cost_of_change())
+    {
+        let effective: u64 = selected
+            .iter()
+            .map(|&i| candidates[i].effective_value)
+            .sum();
+        let input_fees: u64 = selected.iter().map(|&i| candidates[i].fee).sum();
+
+        return Some(Selection {
+            inputs: selected
+                .iter()
+                .map(|&i| candidates[i].utxo.clone())
+                .collect(),
+            change: Amount::default(),
+            fee: Amount::from_sat((base_fee + input_fees + (effective - target)) as i64),
+            waste: Amount::from_sat(waste),
+        });
+    }
+
+    largest_first(&candidates, target, base_fee, params)
+}
+
+/// Returns the indices of the changeless selection with the least waste and
+/// its waste. `candidates` must be sorted by descending effective value.
+fn branch_and_bound(
+    candidates: &[Candidate],
+    target: u64,
+    cost_of_change: u64,
+) -> Option<(Vec<usize>, i64)> {
+    let mut available: u64 = candidates.iter().map(|c| c.effective_value).sum();
+    if available < target {
+        return None;
+    }
+
+    // Once inputs cost more now than later, adding more can only add waste.
+    let waste_grows = candidates.first().map_or(false, |c| c.waste > 0);
+
+    let mut selected = Vec::new();
+    let mut depth = 0;
+    let mut value = 0;
+    let mut waste = 0;
+    let mut best: Option<(Vec<usize>, i64)> = None;
+
+    for _ in 0..BNB_MAX_TRIES {
+        let best_waste = best.as_ref().map_or(i64::max_value(), |b| b.1);
+
+        let backtrack = if value + available < target
+            || value > target + cost_of_change
+            || (waste > best_waste && waste_grows)
+        {
+            true
+        } else if value >= target {
+            let total_waste = waste + (value - target) as i64;
+            if total_waste <= best_waste {
+                best = Some((selected.clone(), total_waste));
+            }
+            true
+        } else {
+            false
+        };
+
+        if backtrack {
+            // Exclude the last included candidate and try the ones after it.
+            let last = match selected.pop() {
+                Some(last) => last,
+                None => break,
+            };
+            for candidate in &candidates[last + 1..depth] {
+                available += candidate.effective_value;
+            }
+            depth = last + 1;
+            value -= candidates[last].effective_value;
+            waste -= candidates[last].waste;
+        } else {
+            let candidate = &candidates[depth];
+            available -= candidate.effective_value;
+            value += candidate.effective_value;
+            waste += candidate.waste;
+            selected.push(depth);
+            depth += 1;
+        }
+    }
+
+    best
+}
+
+fn largest_first(
+    candidates: &[Candidate],
+    target: u64,
+    base_fee: u64,
+    params: &CoinSelectionParams,
+) -> Option<Selection> {
+    let change_fee = sat(params.feerate.fee(params.change_output_vsize));
+
+    let mut inputs = Vec::new();
+    let mut effective = 0;
+    let mut input_fees = 0;
+    let mut waste = 0;
+    for candidate in candidates {
+        inputs.push(candidate.utxo.clone());
+        effective += candidate.effective_value;
+        input_fees += candidate.fee;
+        waste += candidate.waste;
+
+        if effective >= target + change_fee + sat(params.min_change) {
+            return Some(Selection {
+                inputs: inputs,
+                change: Amount::from_sat((effective - target - change_fee) as i64),
+                fee: Amount::from_sat((base_fee + input_fees + change_fee) as i64),
+                waste: Amount::from_sat(waste + params.cost_of_change() as i64),
+            });
+        }
+    }
+
+    if effective < target {
+        return None;
+    }
+
+    // Not enough left for a change output, the excess goes to fees.
+    Some(Selection {
+        inputs: inputs,
+        change: Amount::default(),
+        fee: Amount::from_sat((base_fee + input_fees + (effective - target)) as i64),
+        waste: Amount::from_sat(waste + (effective - target) as i64),
+    })
+}
+
+/// Estimates the virtual size of an input spending `script_pub_key`
+/// (hex-encoded).
+pub(crate) fn estimate_input_vsize(script_pub_key: &str) -> u64 {
+    match (
+        script_pub_key.len(),
+        &script_pub_key[..4.min(script_pub_key.len())],
+    ) {
+        // P2WPKH
+        (44, "0014") => 68,
+        // P2WSH, assuming a 2-of-3 multisig
+        (68, "0020") => 105,
+        // P2TR key path
+        (68, "5120") => 58,
+        // P2SH, assuming P2SH-P2WPKH
+        (46, "a914") => 91,
+        // P2PKH and anything else
+        _ => 148,
+    }
+}
+
+fn sat(amount: Amount) -> u64 {
+    amount.as_sat().max(0) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::util::hash::Sha256dHash;
+
+    use super::*;
+
+    /// A P2WPKH output, costing 68 vbytes to spend.
+    fn utxo(vout: u32, sat: i64) -> ListUnspentResult {
+        ListUnspentResult {
+            txid: Sha256dHash::from_data(&[]).into(),
+            vout: vout,
+            address: None,
+            label: None,
+            script_pub_key: "0014751e76e8199196d454941c45d1b3a323f1433bd6".to_owned(),
+            amount: Amount::from_sat(sat),
+            confirmations: 6,
+            redeem_script: None,
+            spendable: true,
+            solvable: Some(true),
+            safe: Some(true),
+            extra: Default::default(),
+        }
+    }
+
+    fn vouts(selection: &Selection) -> Vec<u32> {
+        selection.inputs.iter().map(|u| u.vout).collect()
+    }
+
+    #[test]
+    fn exact_match() {
+        // 100_000 to the recipient, 42 sat for the base and 68 sat for the
+        // input at 1 sat/vB.
+        let params =
+            CoinSelectionParams::new(Amount::from_sat(100_000), FeeRate::from_sat_per_vb(1));
+        let utxos = vec![utxo(0, 50_000), utxo(1, 100_110), utxo(2, 30_000)];
+
+        let selection = select_coins(&utxos, &params).unwrap();
+        assert_eq!(vouts(&selection), vec![1]);
+        assert_eq!(selection.change, Amount::from_sat(0));
+        assert_eq!(selection.fee, Amount::from_sat(110));
+        // Spending the input now at 1 sat/vB instead of later at 10 sat/vB.
+        assert_eq!(selection.waste, Amount::from_sat(68 - 680));
+    }
+
+    #[test]
+    fn no_changeless_solution() {
+        let params =
+            CoinSelectionParams::new(Amount::from_sat(100_000), FeeRate::from_sat_per_vb(1));
+        let utxos = vec![utxo(0, 50_000), utxo(1, 200_000)];
+
+        // No selection lands within the cost of change of the target, so
+        // the largest input is taken with change.
+        let selection = select_coins(&utxos, &params).unwrap();
+        assert_eq!(vouts(&selection), vec![1]);
+        assert_eq!(selection.fee, Amount::from_sat(42 + 68 + 31));
+        assert_eq!(selection.change, Amount::from_sat(200_000 - 100_000 - 141));
+        assert_eq!(selection.waste, Amount::from_sat(68 - 680 + 31 + 680));
+
+        assert!(select_coins(&utxos[..1], &params).is_none());
+    }
+
+    #[test]
+    fn iteration_limit() {
+        // 100_000 effective value is needed; ten inputs of 10_000 effective
+        // value match it exactly.
+        let params =
+            CoinSelectionParams::new(Amount::from_sat(99_958), FeeRate::from_sat_per_vb(1));
+        let mut utxos: Vec<_> = (0..30).map(|vout| utxo(vout, 10_068)).collect();
+
+        let selection = select_coins(&utxos, &params).unwrap();
+        assert_eq!(selection.inputs.len(), 10);
+        assert_eq!(selection.change, Amount::from_sat(0));
+
+        // With an input of 15_000 effective value tried first, every
+        // selection including it misses the target, and there are too many
+        // of them to reach the ones excluding it.
+        utxos.push(utxo(30, 15_068));
+        let selection = select_coins(&utxos, &params).unwrap();
+        assert_eq!(selection.inputs.len(), 10);
+        assert_eq!(selection.inputs[0].vout, 30);
+        assert_eq!(selection.change, Amount::from_sat(105_000 - 100_000 - 31));
+    }
+
+    #[test]
+    fn waste_tie() {
+        // At the long term fee rate inputs add no waste, so one input of
+        // 60_000 and two of 30_000 effective value are equally good.
+        let params =
+            CoinSelectionParams::new(Amount::from_sat(59_580), FeeRate::from_sat_per_vb(10));
+        let utxos = vec![
+            utxo(0, 60_680),
+            utxo(1, 40_680),
+            utxo(2, 30_680),
+            utxo(3, 30_680),
+        ];
+
+        // The selection found last, excluding the larger inputs, wins.
+        let selection = select_coins(&utxos, &params).unwrap();
+        assert_eq!(vouts(&selection), vec![2, 3]);
+        assert_eq!(selection.change, Amount::from_sat(0));
+        assert_eq!(selection.fee, Amount::from_sat(420 + 2 * 680));
+        assert_eq!(selection.waste, Amount::from_sat(0));
+    }
+}
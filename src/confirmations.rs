@@ -0,0 +1,183 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Waiting for transaction confirmations.
+
+use std::time::Instant;
+
+use blockchain::BlockRef;
+use follower::{ChainFollower, Event};
+use hashes::{BlockHash, Txid};
+use {BitcoinRpc, RpcResult};
+
+/// Upper bound for a single "waitfornewblock" call, in milliseconds.
+const MAX_WAIT_MS: u64 = 10_000;
+
+/// The block including a transaction.
+#[derive(Debug, Clone)]
+pub struct Confirmation {
+    /// Hash of the including block
+    pub block_hash: BlockHash,
+    /// Height of the including block
+    pub height: u64,
+    /// Number of confirmations, counting the including block
+    pub confirmations: u64,
+}
+
+/// The state of a transaction after a check.
+#[derive(Debug, Clone)]
+pub enum Status {
+    /// The transaction is in the mempool
+    Unconfirmed,
+    /// The transaction is in a block but has fewer confirmations than
+    /// requested
+    Confirming(Confirmation),
+    /// The transaction has at least the requested confirmations
+    Confirmed(Confirmation),
+    /// The block with this hash including the transaction was disconnected
+    ReorgedOut(BlockHash),
+    /// The transaction is neither in the active chain nor in the mempool
+    Dropped,
+}
+
+/// The final result of waiting.
+#[derive(Debug, Clone)]
+pub enum Outcome {
+    /// The transaction reached the requested confirmations
+    Confirmed(Confirmation),
+    /// The transaction is neither in the active chain nor in the mempool
+    Dropped,
+    /// The deadline passed first
+    DeadlineExceeded,
+}
+
+/// Waits until a transaction reaches a number of confirmations.
+///
+/// Blocks are scanned through a [`ChainFollower`][], so neither `txindex`
+/// nor a wallet is needed. [`check`][] never waits for new blocks and can be
+/// driven by an event loop; [`wait`][] blocks using "waitfornewblock".
+///
+/// [`ChainFollower`]: ../follower/struct.ChainFollower.html
+/// [`check`]: #method.check
+/// [`wait`]: #method.wait
+pub struct ConfirmationWaiter<'a> {
+    rpc: &'a BitcoinRpc,
+    txid: Txid,
+    confirmations: u64,
+    follower: ChainFollower<'a>,
+    found: Option<BlockRef>,
+}
+
+impl<'a> ConfirmationWaiter<'a> {
+    /// Creates a waiter for `txid` looking for it in the blocks after
+    /// `start_height`, e.g. the height at which it was broadcast.
+    pub fn new(
+        rpc: &'a BitcoinRpc,
+        txid: Txid,
+        confirmations: u64,
+        start_height: u64,
+    ) -> RpcResult<Self> {
+        Ok(ConfirmationWaiter {
+            rpc: rpc,
+            txid: txid,
+            confirmations: confirmations,
+            follower: ChainFollower::from_height(rpc, start_height)?,
+            found: None,
+        })
+    }
+
+    /// Processes the blocks connected since the last check and returns the
+    /// current status of the transaction.
+    pub fn check(&mut self) -> RpcResult<Status> {
+        // Look at the mempool before the blocks, so a transaction mined in
+        // between is not taken as dropped.
+        let in_mempool = match self.rpc.getmempoolentry(&self.txid) {
+            Ok(_) => true,
+            Err(ref e) if e.is_not_found() => false,
+            Err(e) => return Err(e),
+        };
+
+        let mut reorged = None;
+        while let Some(event) = self.follower.poll()? {
+            match event {
+                Event::BlockConnected(block) => {
+                    let txid = self.txid.as_hash();
+                    if self.found.is_none() && block.txdata.iter().any(|tx| tx.txid() == *txid) {
+                        self.found = Some(self.follower.tip().clone());
+                    }
+                }
+                Event::BlockDisconnected(hash) => {
+                    if self.found.as_ref().map(|f| f.hash) == Some(hash) {
+                        self.found = None;
+                        reorged = Some(hash);
+                    }
+                }
+            }
+        }
+
+        let found = match self.found {
+            Some(ref found) => found,
+            None => {
+                return Ok(match reorged {
+                    Some(hash) => Status::ReorgedOut(hash),
+                    None if in_mempool => Status::Unconfirmed,
+                    None => Status::Dropped,
+                })
+            }
+        };
+
+        let confirmation = Confirmation {
+            block_hash: found.hash,
+            height: found.height,
+            confirmations: self.follower.tip().height - found.height + 1,
+        };
+        if confirmation.confirmations >= self.confirmations {
+            Ok(Status::Confirmed(confirmation))
+        } else {
+            Ok(Status::Confirming(confirmation))
+        }
+    }
+
+    /// Blocks until the transaction is confirmed, dropped, or `deadline`
+    /// passes.
+    pub fn wait(&mut self, deadline: Instant) -> RpcResult<Outcome> {
+        self.wait_with(deadline, |_| {})
+    }
+
+    /// Like [`wait`][], calling `on_status` with the status after every
+    /// check, e.g. to report reorgs.
+    ///
+    /// [`wait`]: #method.wait
+    pub fn wait_with<F>(&mut self, deadline: Instant, mut on_status: F) -> RpcResult<Outcome>
+    where
+        F: FnMut(&Status),
+    {
+        loop {
+            let status = self.check()?;
+            on_status(&status);
+
+            match status {
+                Status::Confirmed(confirmation) => return Ok(Outcome::Confirmed(confirmation)),
+                Status::Dropped => return Ok(Outcome::Dropped),
+                _ => {}
+            }
+
+            let now = Instant::now();
+            if now >= deadline {
+                return Ok(Outcome::DeadlineExceeded);
+            }
+
+            let remaining = deadline - now;
+            let remaining_ms =
+                remaining.as_secs() * 1000 + u64::from(remaining.subsec_nanos() / 1_000_000);
+            // A timeout of 0 means no timeout at all.
+            self.rpc
+                .waitfornewblock(remaining_ms.max(1).min(MAX_WAIT_MS))?;
+        }
+    }
+}
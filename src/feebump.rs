@@ -0,0 +1,252 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fee bumping of stuck transactions through RBF and CPFP.
+
+use bitcoin::blockdata::transaction::OutPoint;
+use bitcoin::util::address::Address;
+
+use amount::{Amount, FeeRate};
+use coinselect::estimate_input_vsize;
+use hashes::Txid;
+use txbuilder::{self, TransactionBuilder};
+use wallet::BumpFeeOptions;
+use BitcoinRpc;
+
+/// Virtual size of a child transaction without its input: the overhead
+/// plus a single P2WPKH output.
+const CHILD_BASE_VSIZE: u64 = 11 + 31;
+
+/// Outputs below this value in satoshis are not created.
+const DUST_LIMIT: i64 = 546;
+
+/// The result of replacing a transaction.
+#[derive(Debug, Clone)]
+pub struct RbfBump {
+    /// The id of the replacement ("bumpfee" only)
+    pub txid: Option<Txid>,
+    /// The unsigned replacement as base64-encoded PSBT ("psbtbumpfee" only)
+    pub psbt: Option<String>,
+    /// Fee of the replaced transaction
+    pub original_fee: Amount,
+    /// Fee of the replacement
+    pub fee: Amount,
+    /// Errors the wallet reported while still creating the replacement
+    pub warnings: Vec<String>,
+}
+
+impl RbfBump {
+    /// How much more the replacement pays.
+    pub fn fee_delta(&self) -> Amount {
+        self.fee - self.original_fee
+    }
+}
+
+/// The result of bumping a transaction with a child.
+#[derive(Debug, Clone)]
+pub struct CpfpBump {
+    /// The id of the child transaction
+    pub txid: Txid,
+    /// The output of the parent spent by the child
+    pub spent: OutPoint,
+    /// Fee paid by the child
+    pub fee: Amount,
+    /// Fee rate of the child and its unconfirmed ancestors
+    pub package_feerate: FeeRate,
+}
+
+/// Replaces the wallet transaction `txid` with one paying `feerate`
+/// through "bumpfee".
+pub fn bump_rbf(rpc: &BitcoinRpc, txid: &Txid, feerate: FeeRate) -> FeeBumpResult<RbfBump> {
+    rbf(rpc, txid, feerate, false)
+}
+
+/// Like [`bump_rbf`][], but through "psbtbumpfee", returning the unsigned
+/// replacement as PSBT.
+///
+/// [`bump_rbf`]: fn.bump_rbf.html
+pub fn bump_rbf_psbt(rpc: &BitcoinRpc, txid: &Txid, feerate: FeeRate) -> FeeBumpResult<RbfBump> {
+    rbf(rpc, txid, feerate, true)
+}
+
+fn rbf(rpc: &BitcoinRpc, txid: &Txid, feerate: FeeRate, psbt: bool) -> FeeBumpResult<RbfBump> {
+    let options = BumpFeeOptions {
+        fee_rate: Some(feerate),
+        ..Default::default()
+    };
+
+    let result = if psbt {
+        rpc.psbt_bump_fee(txid, &options)?
+    } else {
+        rpc.bump_fee(txid, &options)?
+    };
+    if result.txid.is_none() && result.psbt.is_none() {
+        return Err(Error::BumpFailed {
+            errors: result.errors,
+        });
+    }
+
+    Ok(RbfBump {
+        txid: result.txid,
+        psbt: result.psbt,
+        original_fee: result.origfee,
+        fee: result.fee,
+        warnings: result.errors,
+    })
+}
+
+/// Bumps the unconfirmed transaction `txid` by spending its largest
+/// output owned by the wallet to `address` in a child transaction, so that
+/// the child and all its unconfirmed ancestors pay `feerate`.
+pub fn bump_cpfp(
+    rpc: &BitcoinRpc,
+    txid: &Txid,
+    address: Address,
+    feerate: FeeRate,
+) -> FeeBumpResult<CpfpBump> {
+    let entry = rpc.getmempoolentry(txid)?;
+    let ancestor_fees = match entry.fees {
+        Some(ref fees) => fees.ancestor,
+        None => return Err(Error::MissingAncestorFees),
+    };
+
+    let output = rpc
+        .list_unspent(0, 0, &[])?
+        .into_iter()
+        .filter(|u| u.txid == *txid && u.spendable)
+        .max_by_key(|u| u.amount)
+        .ok_or(Error::NoSpendableOutput)?;
+
+    let value = output.amount;
+    let child_vsize = CHILD_BASE_VSIZE + estimate_input_vsize(&output.script_pub_key);
+    let package_vsize = entry.ancestorsize + child_vsize;
+
+    let fee = child_fee(feerate, ancestor_fees, entry.ancestorsize, child_vsize);
+    if value < fee + Amount::from_sat(DUST_LIMIT) {
+        return Err(Error::InsufficientValue {
+            value: value,
+            fee: fee,
+        });
+    }
+
+    let spent = OutPoint {
+        txid: txid.into_hash(),
+        vout: output.vout,
+    };
+    let child = TransactionBuilder::new()
+        .input(spent)
+        .pay_to(address, value - fee)
+        .create(rpc)?;
+    let child = txbuilder::sign(rpc, &child)?;
+    let child_txid = txbuilder::broadcast(rpc, &child)?;
+
+    Ok(CpfpBump {
+        txid: child_txid,
+        spent: spent,
+        fee: fee,
+        package_feerate: FeeRate::from_fee(ancestor_fees + fee, package_vsize),
+    })
+}
+
+/// The fee a child of `child_vsize` pays so that it and its unconfirmed
+/// ancestors, of `ancestor_vsize` paying `ancestor_fees`, pay `feerate`.
+///
+/// The child pays at least `feerate` for itself, and for the part of the
+/// ancestors' size their fees don't cover.
+fn child_fee(
+    feerate: FeeRate,
+    ancestor_fees: Amount,
+    ancestor_vsize: u64,
+    child_vsize: u64,
+) -> Amount {
+    let package_fee = feerate.fee(ancestor_vsize + child_vsize);
+    (package_fee - ancestor_fees).max(feerate.fee(child_vsize))
+}
+
+pub type FeeBumpResult<T> = Result<T, Error>;
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Rpc { err: e }
+    }
+}
+
+/// The fee bumping error type
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// A RPC call failed.
+    #[fail(display = "fee bump RPC failed")]
+    Rpc {
+        #[cause]
+        err: ::Error,
+    },
+    /// The wallet refused to replace the transaction.
+    #[fail(display = "fee bump failed: {:?}", errors)]
+    BumpFailed { errors: Vec<String> },
+    /// The node doesn't report ancestor fees (only 0.19+ does).
+    #[fail(display = "mempool entry has no ancestor fees")]
+    MissingAncestorFees,
+    /// The wallet owns no output of the transaction.
+    #[fail(display = "no spendable output to spend from the child")]
+    NoSpendableOutput,
+    /// The output is too small to pay for the child.
+    #[fail(
+        display = "output of {} BTC can't pay a child fee of {} BTC",
+        value, fee
+    )]
+    InsufficientValue { value: Amount, fee: Amount },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn child_fee_covers_package() {
+        let feerate = FeeRate::from_sat_per_vb(10);
+        let cases = vec![
+            // Ancestors paying 1 sat/vB: the child makes up the difference.
+            (200, 200, 110, 200 * 9 + 1100),
+            // Ancestors paying nothing.
+            (0, 200, 110, 3100),
+            // Ancestors already paying `feerate`: the child pays for itself.
+            (2000, 200, 110, 1100),
+            // Ancestors paying more than `feerate` don't lower the child's fee.
+            (10_000, 200, 110, 1100),
+        ];
+        for (ancestor_fees, ancestor_vsize, child_vsize, expected) in cases {
+            assert_eq!(
+                child_fee(
+                    feerate,
+                    Amount::from_sat(ancestor_fees),
+                    ancestor_vsize,
+                    child_vsize
+                ),
+                Amount::from_sat(expected),
+                "{} sat for {} vB",
+                ancestor_fees,
+                ancestor_vsize
+            );
+        }
+
+        // The fee is rounded up.
+        assert_eq!(
+            child_fee(
+                FeeRate::from_sat_per_kvb(1001),
+                Amount::from_sat(0),
+                0,
+                1000
+            ),
+            Amount::from_sat(1001)
+        );
+        assert_eq!(
+            child_fee(FeeRate::from_sat_per_kvb(1500), Amount::from_sat(0), 1, 2),
+            Amount::from_sat(5)
+        );
+    }
+}
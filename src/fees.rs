@@ -0,0 +1,287 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Fee estimation curves.
+
+use amount::FeeRate;
+use mining::EstimateMode;
+use {BitcoinRpc, RpcResult};
+
+/// Confirmation targets queried by default.
+pub const DEFAULT_TARGETS: &[u16] = &[1, 2, 3, 6, 12, 24, 48, 144, 504, 1008];
+
+/// Where the fee rate of a curve point comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+    /// "estimatesmartfee"
+    Node,
+    /// The minimum fee rate of the mempool, as the node had no estimate
+    Mempool,
+    /// The configured floor
+    Floor,
+}
+
+/// The fee rates for a confirmation target.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CurvePoint {
+    /// Confirmation target in blocks
+    pub target: u16,
+    /// Fee rate in `EstimateMode::Economical` mode
+    pub economical: FeeRate,
+    /// Fee rate in `EstimateMode::Conservative` mode
+    pub conservative: FeeRate,
+    /// Where `economical` comes from
+    pub economical_source: Source,
+    /// Where `conservative` comes from
+    pub conservative_source: Source,
+}
+
+/// Fee rates by confirmation target, never increasing with the target.
+#[derive(Debug, Clone)]
+pub struct FeeCurve {
+    points: Vec<CurvePoint>,
+}
+
+impl FeeCurve {
+    /// Builds a curve from points ordered by target, raising the fee rate
+    /// of each to that of the longer targets. Without points, the curve is
+    /// `floor` for every target.
+    fn from_points(mut points: Vec<CurvePoint>, floor: FeeRate) -> FeeCurve {
+        if points.is_empty() {
+            points.push(CurvePoint {
+                target: 1,
+                economical: floor,
+                conservative: floor,
+                economical_source: Source::Floor,
+                conservative_source: Source::Floor,
+            });
+        }
+
+        // Waiting longer never costs more.
+        for i in (0..points.len() - 1).rev() {
+            let next = points[i + 1];
+            let point = &mut points[i];
+            point.economical = point.economical.max(next.economical);
+            point.conservative = point.conservative.max(next.conservative);
+        }
+
+        FeeCurve { points: points }
+    }
+
+    /// The points of the curve, ordered by target.
+    pub fn points(&self) -> &[CurvePoint] {
+        &self.points
+    }
+
+    /// The point for the largest target not greater than `target`, or the
+    /// smallest target if there is none.
+    pub fn point(&self, target: u16) -> &CurvePoint {
+        self.points
+            .iter()
+            .rev()
+            .find(|p| p.target <= target)
+            .unwrap_or(&self.points[0])
+    }
+
+    /// The fee rate to confirm within `target` blocks. `EstimateMode::Unset`
+    /// and unknown modes pick the conservative estimate.
+    pub fn feerate(&self, target: u16, mode: EstimateMode) -> FeeRate {
+        let point = self.point(target);
+        match mode {
+            EstimateMode::Economical => point.economical,
+            EstimateMode::Conservative | EstimateMode::Unset | EstimateMode::Unknown(_) => {
+                point.conservative
+            }
+        }
+    }
+}
+
+/// Builds fee curves from "estimatesmartfee".
+pub struct FeeEstimator<'a> {
+    rpc: &'a BitcoinRpc,
+    targets: Vec<u16>,
+    floor: FeeRate,
+}
+
+impl<'a> FeeEstimator<'a> {
+    /// Creates an estimator for `DEFAULT_TARGETS` with a floor of 1 sat/vB.
+    pub fn new(rpc: &'a BitcoinRpc) -> Self {
+        FeeEstimator {
+            rpc: rpc,
+            targets: DEFAULT_TARGETS.to_vec(),
+            floor: FeeRate::from_sat_per_vb(1),
+        }
+    }
+
+    /// Sets the confirmation targets to query.
+    pub fn set_targets(&mut self, targets: &[u16]) {
+        let mut targets = targets.to_vec();
+        targets.sort();
+        targets.dedup();
+        self.targets = targets;
+    }
+
+    /// Sets the minimum fee rate of the curve.
+    pub fn set_floor(&mut self, floor: FeeRate) {
+        self.floor = floor;
+    }
+
+    /// Queries every target in both estimate modes and builds the curve.
+    ///
+    /// Targets for which the node returns no estimate (e.g. on regtest or
+    /// right after startup) fall back to the minimum fee rate of the
+    /// mempool, or to the floor.
+    pub fn curve(&self) -> RpcResult<FeeCurve> {
+        let mut fallback = None;
+        let mut points = Vec::with_capacity(self.targets.len());
+
+        for &target in &self.targets {
+            let (economical, economical_source) =
+                self.estimate(target, EstimateMode::Economical, &mut fallback)?;
+            let (conservative, conservative_source) =
+                self.estimate(target, EstimateMode::Conservative, &mut fallback)?;
+
+            points.push(CurvePoint {
+                target: target,
+                economical: economical,
+                conservative: conservative,
+                economical_source: economical_source,
+                conservative_source: conservative_source,
+            });
+        }
+
+        Ok(FeeCurve::from_points(points, self.floor))
+    }
+
+    fn estimate(
+        &self,
+        target: u16,
+        mode: EstimateMode,
+        fallback: &mut Option<(FeeRate, Source)>,
+    ) -> RpcResult<(FeeRate, Source)> {
+        let estimate = self.rpc.estimatesmartfee(target, mode)?;
+
+        if let Some(feerate) = estimate.feerate {
+            if feerate >= self.floor {
+                return Ok((feerate, Source::Node));
+            }
+            return Ok((self.floor, Source::Floor));
+        }
+
+        if let Some(fallback) = *fallback {
+            return Ok(fallback);
+        }
+        let mempool = self.mempool_fallback()?;
+        Ok(*fallback.get_or_insert(mempool))
+    }
+
+    fn mempool_fallback(&self) -> RpcResult<(FeeRate, Source)> {
+        let info = self.rpc.getmempoolinfo()?;
+
+        let feerate = match info.minrelaytxfee {
+            Some(minrelaytxfee) => info.mempoolminfee.max(minrelaytxfee),
+            None => info.mempoolminfee,
+        };
+
+        Ok(if feerate > self.floor {
+            (feerate, Source::Mempool)
+        } else {
+            (self.floor, Source::Floor)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(target: u16, economical: u64, conservative: u64) -> CurvePoint {
+        CurvePoint {
+            target: target,
+            economical: FeeRate::from_sat_per_vb(economical),
+            conservative: FeeRate::from_sat_per_vb(conservative),
+            economical_source: Source::Node,
+            conservative_source: Source::Node,
+        }
+    }
+
+    #[test]
+    fn smoothing() {
+        let floor = FeeRate::from_sat_per_vb(1);
+        let cases = vec![
+            (vec![], vec![(1, 1, 1)]),
+            (vec![(2, 5, 8)], vec![(2, 5, 8)]),
+            (
+                vec![(1, 20, 30), (6, 10, 15), (144, 2, 3)],
+                vec![(1, 20, 30), (6, 10, 15), (144, 2, 3)],
+            ),
+            // A longer target estimated higher raises the shorter ones.
+            (
+                vec![(1, 20, 30), (6, 25, 15), (144, 2, 40)],
+                vec![(1, 25, 40), (6, 25, 40), (144, 2, 40)],
+            ),
+        ];
+        for (estimates, expected) in cases {
+            let points = estimates
+                .iter()
+                .map(|&(t, e, c)| point(t, e, c))
+                .collect::<Vec<_>>();
+            let expected = expected
+                .iter()
+                .map(|&(t, e, c)| point(t, e, c))
+                .collect::<Vec<_>>();
+
+            let curve = FeeCurve::from_points(points, floor);
+            assert_eq!(curve.points().len(), expected.len());
+            for (point, expected) in curve.points().iter().zip(&expected) {
+                assert_eq!(point.target, expected.target);
+                assert_eq!(point.economical, expected.economical);
+                assert_eq!(point.conservative, expected.conservative);
+            }
+        }
+
+        let empty = FeeCurve::from_points(vec![], floor);
+        assert_eq!(empty.points()[0].economical_source, Source::Floor);
+        assert_eq!(empty.points()[0].conservative_source, Source::Floor);
+    }
+
+    #[test]
+    fn lookup_falls_back_to_lower_targets() {
+        let curve = FeeCurve::from_points(
+            vec![point(2, 30, 40), point(6, 10, 15), point(144, 2, 3)],
+            FeeRate::from_sat_per_vb(1),
+        );
+
+        let cases = vec![
+            // Below the smallest target: the smallest target.
+            (1, 2),
+            (2, 2),
+            (5, 2),
+            (6, 6),
+            (143, 6),
+            (144, 144),
+            (1008, 144),
+        ];
+        for (target, expected) in cases {
+            assert_eq!(curve.point(target).target, expected, "{}", target);
+        }
+
+        assert_eq!(
+            curve.feerate(10, EstimateMode::Economical),
+            FeeRate::from_sat_per_vb(10)
+        );
+        assert_eq!(
+            curve.feerate(10, EstimateMode::Unset),
+            FeeRate::from_sat_per_vb(15)
+        );
+        assert_eq!(
+            curve.feerate(10, EstimateMode::Unknown("FAST".to_owned())),
+            FeeRate::from_sat_per_vb(15)
+        );
+    }
+}
@@ -0,0 +1,363 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Reorg-aware chain follower.
+
+use std::thread;
+use std::time::Duration;
+
+use bitcoin::blockdata::block::Block;
+use blockchain::BlockRef;
+use hashes::BlockHash;
+use {malformed, BitcoinRpc, RpcResult};
+
+/// A change to the active chain.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The block was connected on top of the previous tip
+    BlockConnected(Block),
+    /// The block with this hash was disconnected from the tip
+    BlockDisconnected(BlockHash),
+}
+
+/// How the follower waits for new blocks once it has caught up.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Wait {
+    /// Sleep for the given duration between polls
+    Sleep(Duration),
+    /// Call "waitfornewblock" with the given timeout in milliseconds
+    /// between polls
+    NewBlock(u64),
+}
+
+/// Follows the active chain of a node, yielding every connected and
+/// disconnected block in order.
+///
+/// On a reorg the follower disconnects blocks one by one back to the fork
+/// point before connecting the blocks of the new chain, however deep the
+/// reorg is.
+pub struct ChainFollower<'a> {
+    rpc: &'a BitcoinRpc,
+    tip: BlockRef,
+    wait: Wait,
+}
+
+impl<'a> ChainFollower<'a> {
+    /// Creates a follower that considers the block `hash` at `height` as
+    /// already processed. The first event is the block after it, or its
+    /// disconnection if it is no longer in the active chain.
+    pub fn new(rpc: &'a BitcoinRpc, height: u64, hash: BlockHash) -> Self {
+        ChainFollower {
+            rpc: rpc,
+            tip: BlockRef {
+                hash: hash,
+                height: height,
+            },
+            wait: Wait::Sleep(Duration::from_secs(1)),
+        }
+    }
+
+    /// Creates a follower starting after the active chain block at
+    /// `height`.
+    pub fn from_height(rpc: &'a BitcoinRpc, height: u64) -> RpcResult<Self> {
+        let hash = rpc.get_blockhash(height)?;
+        Ok(ChainFollower::new(rpc, height, hash))
+    }
+
+    /// Sets how to wait between polls once caught up. Defaults to sleeping
+    /// for one second.
+    pub fn set_wait(&mut self, wait: Wait) {
+        self.wait = wait;
+    }
+
+    /// The last block processed by the follower.
+    pub fn tip(&self) -> &BlockRef {
+        &self.tip
+    }
+
+    /// Returns the next event, or `None` if the follower is at the tip of
+    /// the active chain.
+    pub fn poll(&mut self) -> RpcResult<Option<Event>> {
+        match advance(self.rpc, &self.tip)? {
+            Some((tip, event)) => {
+                self.tip = tip;
+                Ok(Some(event))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Blocks until the next event is available.
+    pub fn next_event(&mut self) -> RpcResult<Event> {
+        loop {
+            if let Some(event) = self.poll()? {
+                return Ok(event);
+            }
+
+            match self.wait {
+                Wait::Sleep(duration) => thread::sleep(duration),
+                Wait::NewBlock(timeout) => {
+                    self.rpc.waitfornewblock(timeout)?;
+                }
+            }
+        }
+    }
+}
+
+/// Where a block is relative to the active chain.
+struct BlockPosition {
+    in_active_chain: bool,
+    prev: Option<BlockHash>,
+    next: Option<BlockHash>,
+}
+
+/// The chain queries the follower needs, so reorgs can be replayed without
+/// a node.
+trait ChainSource {
+    fn best_block_hash(&self) -> RpcResult<BlockHash>;
+    fn block_position(&self, hash: &BlockHash) -> RpcResult<BlockPosition>;
+    fn block(&self, hash: &BlockHash) -> RpcResult<Block>;
+}
+
+impl ChainSource for BitcoinRpc {
+    fn best_block_hash(&self) -> RpcResult<BlockHash> {
+        self.getbestblockhash()
+    }
+
+    fn block_position(&self, hash: &BlockHash) -> RpcResult<BlockPosition> {
+        let info = self.get_block_verbose(hash)?;
+        Ok(BlockPosition {
+            in_active_chain: info.confirmations >= 0,
+            prev: info.previousblockhash,
+            next: info.nextblockhash,
+        })
+    }
+
+    fn block(&self, hash: &BlockHash) -> RpcResult<Block> {
+        self.get(hash)
+    }
+}
+
+/// Returns the tip after the next event from `tip`, and the event, or
+/// `None` if `tip` is the tip of the active chain.
+fn advance<S: ChainSource>(source: &S, tip: &BlockRef) -> RpcResult<Option<(BlockRef, Event)>> {
+    loop {
+        let best = source.best_block_hash()?;
+        if best == tip.hash {
+            return Ok(None);
+        }
+
+        let position = source.block_position(&tip.hash)?;
+
+        if !position.in_active_chain {
+            let prev = position.prev.ok_or_else(|| {
+                malformed(
+                    "getblock",
+                    "previousblockhash",
+                    "",
+                    "missing on a block out of the main chain",
+                )
+            })?;
+            let height = tip.height.checked_sub(1).ok_or_else(|| {
+                malformed(
+                    "getblock",
+                    "confirmations",
+                    "",
+                    "block at height 0 is out of the main chain",
+                )
+            })?;
+
+            let prev = BlockRef {
+                hash: prev,
+                height: height,
+            };
+            return Ok(Some((prev, Event::BlockDisconnected(tip.hash))));
+        }
+
+        let next = match position.next {
+            Some(next) => next,
+            // The best block changed after our `getbestblockhash` call,
+            // look again.
+            None => continue,
+        };
+
+        let block = source.block(&next)?;
+        if block.header.prev_blockhash != tip.hash.into_hash() {
+            // Raced with a reorg; our tip is about to be disconnected.
+            continue;
+        }
+
+        let next = BlockRef {
+            hash: next,
+            height: tip.height + 1,
+        };
+        return Ok(Some((next, Event::BlockConnected(block))));
+    }
+}
+
+impl<'a> Iterator for ChainFollower<'a> {
+    type Item = RpcResult<Event>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        Some(self.next_event())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use bitcoin::network::serialize::BitcoinHash;
+    use bitcoin::util::hash::Sha256dHash;
+
+    use test_utils::header;
+
+    use super::*;
+
+    /// Blocks by hash, and the hashes of the active chain by height.
+    struct FakeChain {
+        blocks: HashMap<BlockHash, Block>,
+        active: Vec<BlockHash>,
+    }
+
+    impl FakeChain {
+        /// A chain of `len` blocks.
+        fn new(len: usize) -> FakeChain {
+            let mut chain = FakeChain {
+                blocks: HashMap::new(),
+                active: Vec::new(),
+            };
+            chain.extend(0, len, 0);
+            chain
+        }
+
+        /// Replaces the active chain above `height` with `len` blocks,
+        /// made distinct by `branch`.
+        fn extend(&mut self, height: usize, len: usize, branch: u32) {
+            self.active.truncate(height);
+            for _ in 0..len {
+                let prev = self
+                    .active
+                    .last()
+                    .map_or(Sha256dHash::from_data(&[]), |h| h.into_hash());
+                let block = Block {
+                    header: header(prev, branch),
+                    txdata: Vec::new(),
+                };
+                let hash = BlockHash::from(block.bitcoin_hash());
+                self.blocks.insert(hash, block);
+                self.active.push(hash);
+            }
+        }
+
+        fn tip(&self) -> BlockRef {
+            BlockRef {
+                hash: *self.active.last().unwrap(),
+                height: self.active.len() as u64 - 1,
+            }
+        }
+    }
+
+    impl ChainSource for FakeChain {
+        fn best_block_hash(&self) -> RpcResult<BlockHash> {
+            Ok(self.tip().hash)
+        }
+
+        fn block_position(&self, hash: &BlockHash) -> RpcResult<BlockPosition> {
+            let height = self.active.iter().position(|h| h == hash);
+            let prev = self.blocks[hash].header.prev_blockhash;
+            Ok(BlockPosition {
+                in_active_chain: height.is_some(),
+                prev: if prev == Sha256dHash::from_data(&[]) {
+                    None
+                } else {
+                    Some(prev.into())
+                },
+                next: height.and_then(|h| self.active.get(h + 1).cloned()),
+            })
+        }
+
+        fn block(&self, hash: &BlockHash) -> RpcResult<Block> {
+            Ok(self.blocks[hash].clone())
+        }
+    }
+
+    /// Follows `chain` from `tip` until it is caught up, returning the
+    /// events as (connected, hash) pairs and the new tip.
+    fn follow(chain: &FakeChain, mut tip: BlockRef) -> (Vec<(bool, BlockHash)>, BlockRef) {
+        let mut events = Vec::new();
+        while let Some((next, event)) = advance(chain, &tip).unwrap() {
+            events.push(match event {
+                Event::BlockConnected(block) => {
+                    assert_eq!(block.bitcoin_hash(), next.hash.into_hash());
+                    assert_eq!(chain.active[next.height as usize], next.hash);
+                    (true, next.hash)
+                }
+                Event::BlockDisconnected(hash) => {
+                    assert_eq!(hash, tip.hash);
+                    (false, hash)
+                }
+            });
+            tip = next;
+        }
+        (events, tip)
+    }
+
+    #[test]
+    fn follows_new_blocks() {
+        let mut chain = FakeChain::new(3);
+        let start = chain.tip();
+        chain.extend(3, 2, 0);
+
+        let (events, tip) = follow(&chain, start);
+        assert_eq!(
+            events,
+            vec![(true, chain.active[3]), (true, chain.active[4])]
+        );
+        assert_eq!(tip.hash, chain.tip().hash);
+        assert_eq!(tip.height, chain.tip().height);
+    }
+
+    #[test]
+    fn reorgs() {
+        // (fork height, new blocks)
+        let cases = vec![(4, 1), (4, 2), (2, 3), (1, 6)];
+        for (fork, len) in cases {
+            let mut chain = FakeChain::new(5);
+            let start = chain.tip();
+            let old = chain.active.clone();
+            chain.extend(fork, len, 1);
+
+            let mut expected = Vec::new();
+            for hash in old[fork..].iter().rev() {
+                expected.push((false, *hash));
+            }
+            for hash in &chain.active[fork..] {
+                expected.push((true, *hash));
+            }
+
+            let (events, tip) = follow(&chain, start);
+            assert_eq!(events, expected, "fork at {} with {} blocks", fork, len);
+            assert_eq!(tip.hash, chain.tip().hash);
+            assert_eq!(tip.height, chain.tip().height);
+        }
+    }
+
+    #[test]
+    fn disconnect_below_height_zero() {
+        let mut chain = FakeChain::new(3);
+        // A tip given with the wrong height.
+        let start = BlockRef {
+            hash: chain.active[2],
+            height: 0,
+        };
+        chain.extend(2, 1, 1);
+
+        assert!(advance(&chain, &start).is_err());
+    }
+}
@@ -9,21 +9,41 @@
 extern crate jsonrpc;
 
 extern crate serde;
+#[macro_use]
+extern crate serde_derive;
 extern crate serde_json;
+extern crate serde_path_to_error;
 
 extern crate bitcoin;
 extern crate bitcoin_rpc_json;
 
+extern crate crossbeam;
+
 extern crate failure;
 #[macro_use]
 extern crate failure_derive;
 
+#[cfg(feature = "zmq")]
+extern crate zmq as libzmq;
+
+/// Satoshi-precise amount and fee rate types.
+pub mod amount {
+    #[doc(inline)]
+    pub use bitcoin_rpc_json::amount::*;
+}
+
 /// Blockchain related RPC result types.
 pub mod blockchain {
     #[doc(inline)]
     pub use bitcoin_rpc_json::blockchain::*;
 }
 
+/// Typed block and transaction hashes.
+pub mod hashes {
+    #[doc(inline)]
+    pub use bitcoin_rpc_json::hashes::*;
+}
+
 /// Mining related RPC result types.
 pub mod mining {
     #[doc(inline)]
@@ -36,55 +56,251 @@ pub mod net {
     pub use bitcoin_rpc_json::net::*;
 }
 
+/// Wallet related RPC result types.
+pub mod wallet {
+    #[doc(inline)]
+    pub use bitcoin_rpc_json::wallet::*;
+}
+
+pub mod blkfile;
+pub mod bulk;
+pub mod coinselect;
+pub mod confirmations;
+pub mod feebump;
+pub mod fees;
+pub mod follower;
+pub mod mempool;
+pub mod merkleblock;
+pub mod rpcerror;
+pub mod stats;
+pub mod txbuilder;
+pub mod utxo;
+pub mod zmq;
+
+#[cfg(test)]
+mod test_utils;
+
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::Mutex;
+
 use jsonrpc::client::Client;
 
-use bitcoin::util::hash::Sha256dHash;
+use bitcoin::blockdata::block::{Block, BlockHeader};
+use bitcoin::blockdata::transaction::{OutPoint, Transaction};
+use bitcoin::network::constants::Network as AddressNetwork;
+use bitcoin::util::address::Address;
+use bitcoin::util::hash::HexError;
+
+use hashes::{BlockHash, Txid};
+use merkleblock::MerkleBlock;
+use rpcerror::{BroadcastRejection, RpcErrorCode, SEND_RAW_TRANSACTION};
+
+/// Number of bytes of the raw response kept in `Error::MalformedResponse`.
+const SNIPPET_LEN: usize = 256;
+
+/// Builds an `Error::MalformedResponse` for the value at `path` of the
+/// response, keeping the start of `raw`.
+fn malformed<E: Into<DecodeError>>(rpc_name: &'static str, path: &str, raw: &str, err: E) -> Error {
+    let mut end = raw.len().min(SNIPPET_LEN);
+    while !raw.is_char_boundary(end) {
+        end -= 1;
+    }
+    let mut snippet = raw[..end].to_owned();
+    if end < raw.len() {
+        snippet.push_str("...");
+    }
+
+    Error::MalformedResponse {
+        rpc_name: rpc_name,
+        err: err.into(),
+        path: path.to_owned(),
+        snippet: snippet,
+    }
+}
 
-fn sha256dhash_from_str(rpc_name: &'static str, hex: &str) -> RpcResult<Sha256dHash> {
-    Ok(Sha256dHash::from_hex(&hex).map_err(|_e| Error::MalformedResponse { rpc_name })?)
+fn address_from_str(rpc_name: &'static str, address: &str) -> RpcResult<Address> {
+    Address::from_str(address).map_err(|e| malformed(rpc_name, ".", address, e))
 }
 
-/// A type that can be used as an id when querying for `Querable`
-// TODO: Unnecessary? Always `Sha256dHash`? --dpc
-pub trait Id {
-    fn to_json_value(&self) -> serde_json::value::Value;
+fn bytes_to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
 }
 
-impl Id for Sha256dHash {
-    fn to_json_value(&self) -> serde_json::value::Value {
-        self.to_string().into()
+/// Decodes a little-endian integer of at most 4 bytes.
+fn u32_from_le(bytes: &[u8]) -> u32 {
+    debug_assert!(bytes.len() <= 4);
+    bytes.iter().rev().fold(0, |n, &b| (n << 8) | u32::from(b))
+}
+
+/// Decodes a little-endian integer of at most 8 bytes.
+fn u64_from_le(bytes: &[u8]) -> u64 {
+    debug_assert!(bytes.len() <= 8);
+    bytes.iter().rev().fold(0, |n, &b| (n << 8) | u64::from(b))
+}
+
+/// An id a `T` can be looked up by.
+///
+/// Implemented for every kind of id of every `Querable` type, so that
+/// `BitcoinRpc::get` only needs the type of the item.
+pub trait Id<T> {
+    /// Looks up the item with this id using `rpc`
+    fn query(&self, rpc: &BitcoinRpc) -> RpcResult<T>;
+}
+
+impl<T: Querable<I>, I> Id<T> for I {
+    fn query(&self, rpc: &BitcoinRpc) -> RpcResult<T> {
+        T::query(rpc, self)
     }
 }
 
-/// A type that can be queried from the Node
-pub trait Querable: Sized {
-    /// Type of the id used to query the item
-    type Id: Id;
+/// The height of a block in the main chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Height(pub u64);
+
+/// A type that can be queried from the Node, by ids of type `I`
+pub trait Querable<I>: Sized {
     /// Query the item using `rpc` and convert to `Self`
-    fn query(rpc: &BitcoinRpc, id: &Self::Id) -> RpcResult<Self>;
+    fn query(rpc: &BitcoinRpc, id: &I) -> RpcResult<Self>;
+}
+
+/// Calls `rpc_name` and decodes the hex string it returns.
+fn query_hex<T>(
+    rpc: &BitcoinRpc,
+    rpc_name: &'static str,
+    args: &[serde_json::Value],
+) -> RpcResult<T>
+where
+    T: for<'a> bitcoin::network::encodable::ConsensusDecodable<
+        bitcoin::network::serialize::RawDecoder<std::io::Cursor<&'a [u8]>>,
+    >,
+{
+    let hex: String = rpc.do_rpc(rpc_name, args)?;
+    let bytes =
+        bitcoin::util::misc::hex_bytes(&hex).map_err(|e| malformed(rpc_name, ".", &hex, e))?;
+    bitcoin::network::serialize::deserialize(&bytes).map_err(|e| malformed(rpc_name, ".", &hex, e))
+}
+
+impl Querable<BlockHash> for Block {
+    fn query(rpc: &BitcoinRpc, id: &BlockHash) -> RpcResult<Self> {
+        query_hex(rpc, "getblock", &[id.to_string().into(), 0.into()])
+    }
+}
+
+impl Querable<Height> for Block {
+    fn query(rpc: &BitcoinRpc, id: &Height) -> RpcResult<Self> {
+        rpc.get(&rpc.get_blockhash(id.0)?)
+    }
+}
+
+impl Querable<BlockHash> for BlockHeader {
+    fn query(rpc: &BitcoinRpc, id: &BlockHash) -> RpcResult<Self> {
+        query_hex(
+            rpc,
+            "getblockheader",
+            &[id.to_string().into(), false.into()],
+        )
+    }
+}
+
+impl Querable<Height> for BlockHeader {
+    fn query(rpc: &BitcoinRpc, id: &Height) -> RpcResult<Self> {
+        rpc.get(&rpc.get_blockhash(id.0)?)
+    }
+}
+
+impl Querable<BlockHash> for blockchain::BlockInfo {
+    fn query(rpc: &BitcoinRpc, id: &BlockHash) -> RpcResult<Self> {
+        rpc.get_block_info::<blockchain::TxIds>(id)
+    }
+}
+
+impl Querable<Height> for blockchain::BlockInfo {
+    fn query(rpc: &BitcoinRpc, id: &Height) -> RpcResult<Self> {
+        rpc.get(&rpc.get_blockhash(id.0)?)
+    }
+}
+
+impl Querable<BlockHash> for blockchain::BlockInfo<blockchain::DecodedTx> {
+    fn query(rpc: &BitcoinRpc, id: &BlockHash) -> RpcResult<Self> {
+        rpc.get_block_info::<blockchain::Transactions>(id)
+    }
 }
 
-impl Querable for bitcoin::blockdata::block::Block {
-    type Id = Sha256dHash;
+impl Querable<Height> for blockchain::BlockInfo<blockchain::DecodedTx> {
+    fn query(rpc: &BitcoinRpc, id: &Height) -> RpcResult<Self> {
+        rpc.get(&rpc.get_blockhash(id.0)?)
+    }
+}
 
-    fn query(rpc: &BitcoinRpc, id: &Self::Id) -> RpcResult<Self> {
-        let rpc_name = "getblock";
-        let hex: String = rpc.do_rpc(rpc_name, &[id.to_json_value(), 0.into()])?;
-        let bytes = bitcoin::util::misc::hex_bytes(&hex)
-            .map_err(|_e| Error::MalformedResponse { rpc_name })?;
-        Ok(bitcoin::network::serialize::deserialize(&bytes).map_err(|e| (rpc_name, e))?)
+impl Querable<Txid> for Transaction {
+    fn query(rpc: &BitcoinRpc, id: &Txid) -> RpcResult<Self> {
+        query_hex(rpc, "getrawtransaction", &[id.to_string().into()])
     }
 }
 
-impl Querable for bitcoin::blockdata::transaction::Transaction {
-    type Id = Sha256dHash;
+/// By txid and the hash of the including block, for nodes without
+/// `-txindex`.
+impl Querable<(Txid, BlockHash)> for Transaction {
+    fn query(rpc: &BitcoinRpc, id: &(Txid, BlockHash)) -> RpcResult<Self> {
+        query_hex(
+            rpc,
+            "getrawtransaction",
+            &[
+                id.0.to_string().into(),
+                false.into(),
+                id.1.to_string().into(),
+            ],
+        )
+    }
+}
 
-    fn query(rpc: &BitcoinRpc, id: &Self::Id) -> RpcResult<Self> {
-        let rpc_name = "getrawtransaction";
-        let hex: String = rpc.do_rpc(rpc_name, &[id.to_json_value()])?;
-        let bytes = bitcoin::util::misc::hex_bytes(&hex)
-            .map_err(|_e| Error::MalformedResponse { rpc_name })?;
-        Ok(bitcoin::network::serialize::deserialize(&bytes).map_err(|e| (rpc_name, e))?)
+impl Querable<Txid> for blockchain::DecodedTx {
+    fn query(rpc: &BitcoinRpc, id: &Txid) -> RpcResult<Self> {
+        rpc.do_rpc("getrawtransaction", &[id.to_string().into(), true.into()])
+    }
+}
+
+impl Querable<(Txid, BlockHash)> for blockchain::DecodedTx {
+    fn query(rpc: &BitcoinRpc, id: &(Txid, BlockHash)) -> RpcResult<Self> {
+        rpc.do_rpc(
+            "getrawtransaction",
+            &[
+                id.0.to_string().into(),
+                true.into(),
+                id.1.to_string().into(),
+            ],
+        )
+    }
+}
+
+impl Querable<Txid> for MerkleBlock {
+    fn query(rpc: &BitcoinRpc, id: &Txid) -> RpcResult<Self> {
+        query_merkle_block(rpc, &[vec![id.to_string()].into()])
+    }
+}
+
+impl Querable<(Txid, BlockHash)> for MerkleBlock {
+    fn query(rpc: &BitcoinRpc, id: &(Txid, BlockHash)) -> RpcResult<Self> {
+        query_merkle_block(
+            rpc,
+            &[vec![id.0.to_string()].into(), id.1.to_string().into()],
+        )
+    }
+}
+
+fn query_merkle_block(rpc: &BitcoinRpc, args: &[serde_json::Value]) -> RpcResult<MerkleBlock> {
+    let rpc_name = "gettxoutproof";
+    let hex: String = rpc.do_rpc(rpc_name, args)?;
+    let bytes =
+        bitcoin::util::misc::hex_bytes(&hex).map_err(|e| malformed(rpc_name, ".", &hex, e))?;
+    MerkleBlock::from_bytes(&bytes).map_err(|e| malformed(rpc_name, ".", &hex, e))
+}
+
+/// The unspent output, or `None` if it is spent or doesn't exist.
+impl Querable<OutPoint> for Option<blockchain::TxOutInfo> {
+    fn query(rpc: &BitcoinRpc, id: &OutPoint) -> RpcResult<Self> {
+        rpc.do_rpc("gettxout", &[id.txid.to_string().into(), id.vout.into()])
     }
 }
 
@@ -93,6 +309,7 @@ pub type RpcResult<T> = Result<T, Error>;
 /// A Handle to a Bitcoin JSON-RPC connection
 pub struct BitcoinRpc {
     client: Client,
+    network: Mutex<Option<blockchain::Network>>,
 }
 
 impl BitcoinRpc {
@@ -104,6 +321,7 @@ impl BitcoinRpc {
 
         BitcoinRpc {
             client: Client::new(url, user, pass),
+            network: Mutex::new(None),
         }
     }
 
@@ -112,15 +330,82 @@ impl BitcoinRpc {
         rpc_name: &'static str,
         args: &[serde_json::value::Value],
     ) -> RpcResult<T> {
-        Ok(self
+        let raw: serde_json::Value = self
             .client
             .do_rpc(rpc_name, args)
-            .map_err(|e| (rpc_name, e))?)
+            .map_err(|e| (rpc_name, e))?;
+
+        serde_path_to_error::deserialize(&raw).map_err(|e| {
+            let path = e.path().to_string();
+            malformed(rpc_name, &path, &raw.to_string(), e.into_inner())
+        })
+    }
+
+    /// Returns the network of the node, from the "chain" reported by
+    /// "getblockchaininfo". Queried once and cached.
+    pub fn network(&self) -> RpcResult<blockchain::Network> {
+        let mut cached = self.network.lock().unwrap();
+        if let Some(ref network) = *cached {
+            return Ok(network.clone());
+        }
+
+        let network = self.getblockchaininfo()?.chain;
+        *cached = Some(network.clone());
+        Ok(network)
     }
 
-    /// Query an object implementing `Querable` type
-    pub fn get<T: Querable>(&self, id: &<T as Querable>::Id) -> RpcResult<T> {
-        T::query(self, &id)
+    /// Fails with `Error::WrongNetwork` if `address` isn't valid on the
+    /// network of the node, or if the node is on an unknown chain.
+    pub fn check_address(&self, address: &Address) -> RpcResult<()> {
+        let network = self.network()?;
+        // Regtest shares its base58 prefixes with testnet.
+        let valid = match network.address_network() {
+            Some(AddressNetwork::Regtest) => {
+                address.network == AddressNetwork::Regtest
+                    || address.network == AddressNetwork::Testnet
+            }
+            Some(expected) => address.network == expected,
+            None => false,
+        };
+        if !valid {
+            return Err(Error::WrongNetwork {
+                address: address.to_string(),
+                network: network,
+            });
+        }
+        Ok(())
+    }
+
+    /// Query an object implementing `Querable` type, by any kind of id
+    /// of the type.
+    ///
+    /// ```no_run
+    /// # extern crate bitcoin;
+    /// # extern crate bitcoin_rpc;
+    /// # use bitcoin::blockdata::block::{Block, BlockHeader};
+    /// # use bitcoin::network::serialize::BitcoinHash;
+    /// # use bitcoin_rpc::hashes::BlockHash;
+    /// # use bitcoin_rpc::{BitcoinRpc, Height};
+    /// # fn main() {
+    /// let rpc = BitcoinRpc::new("http://localhost:8332".into(), None, None);
+    /// let header = rpc.get::<BlockHeader>(&Height(0)).unwrap();
+    /// let block = rpc.get::<Block>(&BlockHash::from(header.bitcoin_hash())).unwrap();
+    /// # }
+    /// ```
+    pub fn get<T>(&self, id: &impl Id<T>) -> RpcResult<T> {
+        id.query(self)
+    }
+
+    /// Query the items with `ids`, with up to `concurrency` requests in
+    /// flight, and return them in the order of `ids`. Fails with the error
+    /// of the first failed id; `bulk::get_many_iter` streams the items and
+    /// reports errors per id instead.
+    pub fn get_many<T: Send>(
+        &self,
+        ids: &[impl Id<T> + Sync],
+        concurrency: usize,
+    ) -> RpcResult<Vec<T>> {
+        bulk::get_many(self, ids, concurrency)
     }
 
     // blockchain
@@ -131,9 +416,8 @@ impl BitcoinRpc {
     }
 
     /// Returns the hash of the best (tip) block in the longest blockchain.
-    pub fn getbestblockhash(&self) -> RpcResult<Sha256dHash> {
-        let v: String = self.do_rpc("getbestblockhash", &[])?;
-        sha256dhash_from_str("getbestblockhash", &v)
+    pub fn getbestblockhash(&self) -> RpcResult<BlockHash> {
+        self.do_rpc("getbestblockhash", &[])
     }
 
     /// Waits for a specific new block and returns useful info about it.
@@ -146,8 +430,7 @@ impl BitcoinRpc {
     pub fn waitfornewblock(&self, timeout: u64) -> RpcResult<blockchain::BlockRef> {
         let params = vec![serde_json::to_value(timeout).unwrap()];
 
-        let v: blockchain::SerdeBlockRef = self.do_rpc("waitfornewblock", &params)?;
-        Ok(v.into())
+        self.do_rpc("waitfornewblock", &params)
     }
 
     /// Waits for a specific new block and returns useful info about it.
@@ -158,14 +441,15 @@ impl BitcoinRpc {
     /// 1. `blockhash`: Block hash to wait for.
     /// 2. `timeout`: Time in milliseconds to wait for a response. 0
     /// indicates no timeout.
-    pub fn waitforblock(&self, blockhash: String, timeout: u64) -> RpcResult<blockchain::BlockRef> {
-        let params = vec![
-            serde_json::to_value(blockhash).unwrap(),
-            serde_json::to_value(timeout).unwrap(),
-        ];
-
-        let v: blockchain::SerdeBlockRef = self.do_rpc("waitforblock", &params)?;
-        Ok(v.into())
+    pub fn waitforblock(
+        &self,
+        blockhash: &BlockHash,
+        timeout: u64,
+    ) -> RpcResult<blockchain::BlockRef> {
+        self.do_rpc(
+            "waitforblock",
+            &[blockhash.to_string().into(), timeout.into()],
+        )
     }
 
     /// Returns a data structure containing various state info regarding
@@ -174,6 +458,42 @@ impl BitcoinRpc {
         self.do_rpc("getblockchaininfo", &[])
     }
 
+    /// Returns the state of the soft fork deployments at the tip (23.0+).
+    pub fn getdeploymentinfo(&self) -> RpcResult<blockchain::DeploymentInfo> {
+        self.do_rpc("getdeploymentinfo", &[])
+    }
+
+    /// Returns the state of the soft fork deployments, from
+    /// "getblockchaininfo" or, on 23.0+, "getdeploymentinfo".
+    pub fn deployments(&self) -> RpcResult<HashMap<String, blockchain::Deployment>> {
+        let deployments = self.getblockchaininfo()?.deployments();
+        if !deployments.is_empty() {
+            return Ok(deployments);
+        }
+
+        Ok(self.getdeploymentinfo()?.deployments)
+    }
+
+    /// Returns the txids of all transactions in the mempool.
+    pub fn getrawmempool(&self) -> RpcResult<Vec<Txid>> {
+        self.do_rpc("getrawmempool", &[])
+    }
+
+    /// Returns the entries of all transactions in the mempool, by txid.
+    pub fn getrawmempool_verbose(&self) -> RpcResult<HashMap<Txid, blockchain::MempoolEntry>> {
+        self.do_rpc("getrawmempool", &[true.into()])
+    }
+
+    /// Returns details on the active state of the mempool.
+    pub fn getmempoolinfo(&self) -> RpcResult<blockchain::MempoolInfo> {
+        self.do_rpc("getmempoolinfo", &[])
+    }
+
+    /// Returns mempool data for the given transaction.
+    pub fn getmempoolentry(&self, txid: &Txid) -> RpcResult<blockchain::MempoolEntry> {
+        self.do_rpc("getmempoolentry", &[txid.to_string().into()])
+    }
+
     // mining
 
     pub fn estimatesmartfee<E>(
@@ -204,8 +524,8 @@ impl BitcoinRpc {
     /// Requests that a ping be sent to all other nodes, to measure ping
     /// time.
     ///
-    /// Results provided in `getpeerinfo`, `pingtime` and `pingwait` fields
-    /// are decimal seconds.
+    /// Results are provided in the `pingtime` and `pingwait` fields of
+    /// `getpeerinfo`.
     ///
     /// Ping command is handled in queue with all other commands, so it
     /// measures processing backlog, not just network ping.
@@ -249,64 +569,97 @@ impl BitcoinRpc {
         self.do_rpc("getnetworkinfo", &[])
     }
 
+    // zmq
+
+    /// Returns information about the active ZeroMQ notifications.
+    pub fn getzmqnotifications(&self) -> RpcResult<Vec<zmq::ZmqNotification>> {
+        self.do_rpc("getzmqnotifications", &[])
+    }
+
     /// Mark a block as invalid by `block_hash`
-    pub fn invalidate_block(&self, block_hash: &Sha256dHash) -> RpcResult<()> {
+    pub fn invalidate_block(&self, block_hash: &BlockHash) -> RpcResult<()> {
         self.do_rpc("invalidateblock", &[block_hash.to_string().into()])
     }
 
     /// Get the hex-consensus-encoded block by `block_hash`
-    pub fn get_block(&self, block_hash: &Sha256dHash) -> RpcResult<String> {
+    pub fn get_block(&self, block_hash: &BlockHash) -> RpcResult<String> {
         self.do_rpc("getblock", &[block_hash.to_string().into(), 0.into()])
     }
 
-    /// Get block by `block_hash`
-    pub fn get_block_verbose(&self, block_hash: &Sha256dHash) -> RpcResult<blockchain::BlockInfo> {
-        self.do_rpc("getblock", &[block_hash.to_string().into(), 1.into()])
+    /// Get block by `block_hash`, with its txids
+    pub fn get_block_verbose(&self, block_hash: &BlockHash) -> RpcResult<blockchain::BlockInfo> {
+        self.get_block_info::<blockchain::TxIds>(block_hash)
+    }
+
+    /// Get block by `block_hash`, with its transactions as selected by the
+    /// verbosity `V`, e.g. `blockchain::Transactions`
+    pub fn get_block_info<V: blockchain::GetBlockVerbosity>(
+        &self,
+        block_hash: &BlockHash,
+    ) -> RpcResult<blockchain::BlockInfo<V::Tx>> {
+        self.do_rpc("getblock", &[block_hash.to_string().into(), V::LEVEL.into()])
     }
 
     /// Generate new address under own control
-    pub fn get_new_address(&self, account: String) -> RpcResult<String> {
-        self.do_rpc("getnewaddress", &[account.into()])
+    pub fn get_new_address(&self, account: String) -> RpcResult<Address> {
+        let address: String = self.do_rpc("getnewaddress", &[account.into()])?;
+        address_from_str("getnewaddress", &address)
     }
 
     /// Dump private key of an `address`
-    pub fn dump_priv_key(&self, address: String) -> RpcResult<String> {
-        self.do_rpc("dumpprivkey", &[address.into()])
+    pub fn dump_priv_key(&self, address: &Address) -> RpcResult<PrivkeyString> {
+        self.check_address(address)?;
+        self.do_rpc("dumpprivkey", &[address.to_string().into()])
     }
 
-    /// Mine `block_num` blocks and pay coinbase to `address`
+    /// Mine `block_num` blocks and pay coinbase to `address`. Refused on
+    /// any network but regtest.
     ///
     /// Returns hashes of the generated blocks
     pub fn generate_to_address(
         &self,
         block_num: u64,
-        address: String,
-    ) -> RpcResult<Vec<Sha256dHash>> {
-        let v: Vec<String> =
-            self.do_rpc("generatetoaddress", &[block_num.into(), address.into()])?;
-
-        Ok(v.into_iter()
-            .map(|v| sha256dhash_from_str("generatetoaddress", &v))
-            .collect::<RpcResult<Vec<Sha256dHash>>>()?)
+        address: &Address,
+    ) -> RpcResult<Vec<BlockHash>> {
+        let network = self.network()?;
+        if network != blockchain::Network::Regtest {
+            return Err(Error::UnsupportedNetwork {
+                rpc_name: "generatetoaddress",
+                network: network,
+            });
+        }
+        self.check_address(address)?;
+        self.do_rpc(
+            "generatetoaddress",
+            &[block_num.into(), address.to_string().into()],
+        )
     }
 
     /// Get block hash at a given height
-    pub fn get_blockhash(&self, height: u64) -> RpcResult<Sha256dHash> {
-        let hex_string: String = self.do_rpc("getblockhash", &[height.into()])?;
-        sha256dhash_from_str("getblockhash", &hex_string)
+    pub fn get_blockhash(&self, height: u64) -> RpcResult<BlockHash> {
+        self.do_rpc("getblockhash", &[height.into()])
     }
 
+    /// Creates an unsigned transaction spending `ins` and paying `outs`,
+    /// in order.
     pub fn create_raw_transaction(
         &self,
         ins: &[self::blockchain::TxInInfoCreateTx],
-        outs: &std::collections::HashMap<AddressString, BalanceFloat>,
+        outs: &[(Address, amount::Amount)],
     ) -> RpcResult<RawTxString> {
+        // An array of single-key objects keeps the order of `outs` and
+        // allows paying an address twice, unlike a single object.
+        let mut outputs: Vec<serde_json::Value> = Vec::with_capacity(outs.len());
+        for &(ref address, amount) in outs {
+            self.check_address(address)?;
+            let mut output = serde_json::value::Map::new();
+            output.insert(address.to_string(), amount.to_string().into());
+            outputs.push(output.into());
+        }
+
         self.do_rpc(
             "createrawtransaction",
-            &[
-                serde_json::to_value(ins).unwrap(),
-                serde_json::to_value(outs).unwrap(),
-            ],
+            &[serde_json::to_value(ins).unwrap(), outputs.into()],
         )
     }
 
@@ -326,34 +679,156 @@ impl BitcoinRpc {
         )
     }
 
-    pub fn send_raw_transaction(&mut self, tx: RawTransactionString) -> RpcResult<RawTxString> {
-        self.do_rpc("sendrawtransaction", &[tx.into()])
+    /// Returns the unspent outputs of the wallet with between `minconf` and
+    /// `maxconf` confirmations, only those paying to `addresses` unless it
+    /// is empty.
+    pub fn list_unspent(
+        &self,
+        minconf: u64,
+        maxconf: u64,
+        addresses: &[Address],
+    ) -> RpcResult<Vec<wallet::ListUnspentResult>> {
+        let mut params = vec![minconf.into(), maxconf.into()];
+        if !addresses.is_empty() {
+            let mut strings = Vec::with_capacity(addresses.len());
+            for address in addresses {
+                self.check_address(address)?;
+                strings.push(serde_json::Value::from(address.to_string()));
+            }
+            params.push(strings.into());
+        }
+
+        self.do_rpc("listunspent", &params)
+    }
+
+    /// Bumps the fee of a wallet transaction by replacing it (BIP125).
+    pub fn bump_fee(
+        &self,
+        txid: &Txid,
+        options: &wallet::BumpFeeOptions,
+    ) -> RpcResult<wallet::BumpFeeResult> {
+        self.do_rpc(
+            "bumpfee",
+            &[txid.to_string().into(), serde_json::to_value(options).unwrap()],
+        )
+    }
+
+    /// Like [`bump_fee`][], but returns an unsigned PSBT of the replacement
+    /// instead of signing and broadcasting it.
+    ///
+    /// [`bump_fee`]: #method.bump_fee
+    pub fn psbt_bump_fee(
+        &self,
+        txid: &Txid,
+        options: &wallet::BumpFeeOptions,
+    ) -> RpcResult<wallet::BumpFeeResult> {
+        self.do_rpc(
+            "psbtbumpfee",
+            &[txid.to_string().into(), serde_json::to_value(options).unwrap()],
+        )
+    }
+
+    /// Adds inputs to a transaction until it has enough in value to meet
+    /// its out value, adding a change output if needed.
+    pub fn fund_raw_transaction(
+        &self,
+        tx: RawTxString,
+        options: &blockchain::FundRawTransactionOptions,
+    ) -> RpcResult<blockchain::FundRawTransactionResult> {
+        self.do_rpc(
+            "fundrawtransaction",
+            &[tx.into(), serde_json::to_value(options).unwrap()],
+        )
+    }
+
+    /// Signs the inputs of a transaction with the keys of the wallet.
+    pub fn sign_raw_transaction_with_wallet(
+        &self,
+        tx: RawTxString,
+    ) -> RpcResult<self::blockchain::SignedRawTransaction> {
+        self.do_rpc("signrawtransactionwithwallet", &[tx.into()])
+    }
+
+    /// Broadcasts the hex-consensus-encoded transaction `tx` and returns
+    /// its txid.
+    pub fn send_raw_transaction(&self, tx: RawTransactionString) -> RpcResult<Txid> {
+        self.do_rpc(SEND_RAW_TRANSACTION, &[tx.into()])
     }
 
     /// Get the hex-consensus-encoded transaction by `txid`
-    pub fn get_raw_transaction(&self, hash: &Sha256dHash) -> RpcResult<String> {
-        self.do_rpc("getrawtransaction", &[hash.to_string().into(), 0.into()])
+    pub fn get_raw_transaction(&self, txid: &Txid) -> RpcResult<String> {
+        self.do_rpc("getrawtransaction", &[txid.to_string().into(), 0.into()])
     }
 }
 
 pub type RawTransactionString = String;
 pub type AddressString = String;
 pub type PrivkeyString = String;
-pub type BalanceFloat = f64;
 pub type RawTxString = String;
 
 impl From<(&'static str, jsonrpc::Error)> for Error {
     fn from(e: (&'static str, jsonrpc::Error)) -> Error {
-        Error::JsonRpc {
-            rpc_name: e.0,
-            err: e.1,
+        match e.1 {
+            // The response doesn't match the expected result type.
+            jsonrpc::Error::Json(err) => malformed(e.0, ".", "", err),
+            jsonrpc::Error::Rpc(err) => Error::Server {
+                rpc_name: e.0,
+                code: RpcErrorCode::from_code(err.code),
+                message: err.message,
+                data: err.data,
+            },
+            err => Error::JsonRpc {
+                rpc_name: e.0,
+                err: err,
+            },
         }
     }
 }
 
 impl From<(&'static str, bitcoin::network::serialize::Error)> for Error {
     fn from(e: (&'static str, bitcoin::network::serialize::Error)) -> Error {
-        Error::MalformedResponse { rpc_name: e.0 }
+        malformed(e.0, ".", "", e.1)
+    }
+}
+
+/// Why a response could not be decoded.
+#[derive(Debug, Fail)]
+pub enum DecodeError {
+    /// The JSON doesn't match the expected type
+    #[fail(display = "{}", _0)]
+    Json(#[cause] serde_json::Error),
+    /// Invalid hex or consensus encoding, or an invalid address
+    #[fail(display = "{}", _0)]
+    Bitcoin(#[cause] bitcoin::network::serialize::Error),
+    /// An invalid hash
+    #[fail(display = "{}", _0)]
+    Hash(#[cause] HexError),
+    /// The response is inconsistent
+    #[fail(display = "{}", _0)]
+    Invalid(&'static str),
+}
+
+impl From<serde_json::Error> for DecodeError {
+    fn from(e: serde_json::Error) -> DecodeError {
+        DecodeError::Json(e)
+    }
+}
+
+impl From<bitcoin::network::serialize::Error> for DecodeError {
+    fn from(e: bitcoin::network::serialize::Error) -> DecodeError {
+        DecodeError::Bitcoin(e)
+    }
+}
+
+impl From<HexError> for DecodeError {
+    fn from(e: HexError) -> DecodeError {
+        DecodeError::Hash(e)
+    }
+}
+
+impl From<&'static str> for DecodeError {
+    fn from(e: &'static str) -> DecodeError {
+        DecodeError::Invalid(e)
     }
 }
 /// The error type
@@ -366,7 +841,81 @@ pub enum Error {
         #[cause]
         err: jsonrpc::Error,
     },
+    /// The server answered with an error.
+    #[fail(display = "JsonRpc {} failed with code {}: {}", rpc_name, code, message)]
+    Server {
+        rpc_name: &'static str,
+        code: RpcErrorCode,
+        message: String,
+        data: Option<serde_json::Value>,
+    },
     /// The received response format is malformed.
-    #[fail(display = "JsonRpc {} response format is invalid", rpc_name)]
-    MalformedResponse { rpc_name: &'static str },
+    #[fail(display = "JsonRpc {} response is invalid at {}: {}", rpc_name, path, err)]
+    MalformedResponse {
+        rpc_name: &'static str,
+        #[cause]
+        err: DecodeError,
+        /// Path of the invalid value in the response, "." for the root
+        path: String,
+        /// The start of the raw response, if available
+        snippet: String,
+    },
+    /// An address is not valid on the network of the node.
+    #[fail(display = "address {} is not valid on {:?}", address, network)]
+    WrongNetwork {
+        address: String,
+        network: blockchain::Network,
+    },
+    /// The RPC is refused on the network of the node.
+    #[fail(display = "JsonRpc {} is not allowed on {:?}", rpc_name, network)]
+    UnsupportedNetwork {
+        rpc_name: &'static str,
+        network: blockchain::Network,
+    },
+    /// The wallet could not sign every input of a transaction.
+    #[fail(display = "transaction signing is incomplete")]
+    IncompleteSignature,
+    /// A thread querying items panicked.
+    #[fail(display = "bulk query worker stopped")]
+    WorkerStopped,
+}
+
+impl Error {
+    /// The code of the error returned by the server, if any.
+    pub fn code(&self) -> Option<RpcErrorCode> {
+        match *self {
+            Error::Server { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+
+    /// Returns `true` if the node is still starting up.
+    pub fn is_warmup(&self) -> bool {
+        self.code().map_or(false, |code| code.is_warmup())
+    }
+
+    /// Returns `true` if the requested item doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        self.code().map_or(false, |code| code.is_not_found())
+    }
+
+    /// Why "sendrawtransaction" rejected the transaction, if this is such
+    /// an error.
+    pub fn rejection(&self) -> Option<BroadcastRejection> {
+        match *self {
+            Error::Server {
+                rpc_name: SEND_RAW_TRANSACTION,
+                code,
+                ref message,
+                ..
+            } => match code {
+                RpcErrorCode::VerifyAlreadyInChain => Some(BroadcastRejection::AlreadyInChain),
+                RpcErrorCode::VerifyError | RpcErrorCode::VerifyRejected => {
+                    Some(BroadcastRejection::from_message(message))
+                }
+                _ => None,
+            },
+            _ => None,
+        }
+    }
 }
@@ -0,0 +1,304 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Mempool watcher.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction};
+
+use blockchain::MempoolEntry;
+use hashes::{BlockHash, Txid};
+use {bulk, BitcoinRpc, Querable, RpcResult};
+
+/// Number of recent blocks remembered to tell mined transactions apart.
+const RECENT_BLOCKS: usize = 6;
+
+/// Number of new transactions fetched concurrently.
+const CONCURRENCY: usize = 8;
+
+/// The txid of a mempool transaction, which may leave the mempool before its
+/// entry and transaction are fetched.
+struct MempoolTxid(Txid);
+
+impl Querable<MempoolTxid> for Option<(MempoolEntry, Transaction)> {
+    fn query(rpc: &BitcoinRpc, txid: &MempoolTxid) -> RpcResult<Self> {
+        let fetched = rpc
+            .getmempoolentry(&txid.0)
+            .and_then(|entry| rpc.get(&txid.0).map(|tx| (entry, tx)));
+        match fetched {
+            Ok(fetched) => Ok(Some(fetched)),
+            Err(ref e) if e.is_not_found() => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Why a transaction left the mempool.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RemovalReason {
+    /// The transaction was mined into the block with this hash
+    Mined(BlockHash),
+    /// A conflicting transaction with this txid spent one of its inputs
+    Replaced(Txid),
+    /// The transaction was evicted, expired or otherwise dropped
+    Evicted,
+}
+
+/// A change to the mempool.
+#[derive(Debug, Clone)]
+pub enum Event {
+    /// The transaction entered the mempool
+    Added(Txid, MempoolEntry),
+    /// The transaction left the mempool
+    Removed(Txid, RemovalReason),
+}
+
+struct RecentBlock {
+    hash: BlockHash,
+    txids: HashSet<Txid>,
+    spends: HashMap<OutPoint, Txid>,
+}
+
+/// Watches the mempool of a node by diffing "getrawmempool" snapshots.
+///
+/// Each poll takes the txids of the whole mempool in one request, and only
+/// fetches the entries and transactions of those that are new since the
+/// previous snapshot, several at a time.
+pub struct MempoolWatcher<'a> {
+    rpc: &'a BitcoinRpc,
+    txs: HashMap<Txid, Vec<OutPoint>>,
+    spenders: HashMap<OutPoint, Txid>,
+    best: Option<BlockHash>,
+    recent: VecDeque<RecentBlock>,
+}
+
+impl<'a> MempoolWatcher<'a> {
+    /// Creates a watcher. The first poll reports every transaction in the
+    /// mempool as added.
+    pub fn new(rpc: &'a BitcoinRpc) -> Self {
+        MempoolWatcher {
+            rpc: rpc,
+            txs: HashMap::new(),
+            spenders: HashMap::new(),
+            best: None,
+            recent: VecDeque::new(),
+        }
+    }
+
+    /// Number of transactions in the last snapshot.
+    pub fn len(&self) -> usize {
+        self.txs.len()
+    }
+
+    /// Returns `true` if the last snapshot was empty.
+    pub fn is_empty(&self) -> bool {
+        self.txs.is_empty()
+    }
+
+    /// Takes a new snapshot and returns the changes since the previous one.
+    /// Removals are reported before additions.
+    pub fn poll(&mut self) -> RpcResult<Vec<Event>> {
+        let snapshot: HashSet<Txid> = self.rpc.getrawmempool()?.into_iter().collect();
+        // Blocks are looked at after the snapshot so that every transaction
+        // missing from it that was mined is found in `recent`.
+        self.update_recent_blocks()?;
+
+        let new_txids: Vec<MempoolTxid> = snapshot
+            .iter()
+            .filter(|txid| !self.txs.contains_key(txid))
+            .map(|txid| MempoolTxid(*txid))
+            .collect();
+        let new_txs: Vec<Option<(MempoolEntry, Transaction)>> =
+            bulk::get_many(self.rpc, &new_txids, CONCURRENCY)?;
+
+        let mut added = Vec::new();
+        for (txid, tx) in new_txids.into_iter().zip(new_txs) {
+            let txid = txid.0;
+            // The transaction may have left the mempool since the snapshot;
+            // it then never gets reported.
+            let (entry, tx) = match tx {
+                Some(fetched) => fetched,
+                None => continue,
+            };
+
+            let inputs: Vec<OutPoint> = tx.input.iter().map(|i| i.previous_output).collect();
+            for outpoint in &inputs {
+                self.spenders.insert(*outpoint, txid);
+            }
+            self.txs.insert(txid, inputs);
+            added.push(Event::Added(txid, entry));
+        }
+
+        let removed: Vec<Txid> = self
+            .txs
+            .keys()
+            .filter(|txid| !snapshot.contains(txid))
+            .cloned()
+            .collect();
+
+        let mut events = Vec::with_capacity(removed.len() + added.len());
+        for txid in removed {
+            let inputs = self.txs.remove(&txid).unwrap_or_default();
+            let reason = self.removal_reason(&txid, &inputs);
+
+            for outpoint in &inputs {
+                if self.spenders.get(outpoint) == Some(&txid) {
+                    self.spenders.remove(outpoint);
+                }
+            }
+
+            events.push(Event::Removed(txid, reason));
+        }
+        events.extend(added);
+
+        Ok(events)
+    }
+
+    fn removal_reason(&self, txid: &Txid, inputs: &[OutPoint]) -> RemovalReason {
+        if let Some(block) = self.recent.iter().find(|b| b.txids.contains(txid)) {
+            return RemovalReason::Mined(block.hash);
+        }
+
+        for outpoint in inputs {
+            match self.spenders.get(outpoint) {
+                Some(spender) if spender != txid => return RemovalReason::Replaced(*spender),
+                _ => {}
+            }
+
+            for block in &self.recent {
+                if let Some(spender) = block.spends.get(outpoint) {
+                    return RemovalReason::Replaced(*spender);
+                }
+            }
+        }
+
+        RemovalReason::Evicted
+    }
+
+    /// Fetches the blocks connected since the last poll, up to
+    /// `RECENT_BLOCKS` deep.
+    fn update_recent_blocks(&mut self) -> RpcResult<()> {
+        let best = self.rpc.getbestblockhash()?;
+        if self.best == Some(best) {
+            return Ok(());
+        }
+
+        let mut new_blocks = Vec::new();
+        let mut hash = best;
+        while Some(hash) != self.best && new_blocks.len() < RECENT_BLOCKS {
+            if self.recent.iter().any(|b| b.hash == hash) {
+                break;
+            }
+
+            let block: Block = self.rpc.get(&hash)?;
+            let prev = BlockHash::from(block.header.prev_blockhash);
+            new_blocks.push((hash, block));
+            hash = prev;
+        }
+
+        for (hash, block) in new_blocks.into_iter().rev() {
+            let mut txids = HashSet::with_capacity(block.txdata.len());
+            let mut spends = HashMap::new();
+            for tx in &block.txdata {
+                let txid = Txid::from(tx.txid());
+                for input in &tx.input {
+                    spends.insert(input.previous_output, txid);
+                }
+                txids.insert(txid);
+            }
+
+            self.recent.push_back(RecentBlock {
+                hash: hash,
+                txids: txids,
+                spends: spends,
+            });
+        }
+
+        while self.recent.len() > RECENT_BLOCKS {
+            self.recent.pop_front();
+        }
+        self.best = Some(best);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::util::hash::Sha256dHash;
+
+    use super::*;
+
+    fn hash<H: From<Sha256dHash>>(n: u8) -> H {
+        Sha256dHash::from_data(&[n]).into()
+    }
+
+    fn outpoint(n: u8) -> OutPoint {
+        OutPoint {
+            txid: hash(n),
+            vout: 0,
+        }
+    }
+
+    fn recent_block(n: u8, txids: &[Txid], spends: &[(OutPoint, Txid)]) -> RecentBlock {
+        RecentBlock {
+            hash: hash(n),
+            txids: txids.iter().cloned().collect(),
+            spends: spends.iter().cloned().collect(),
+        }
+    }
+
+    #[test]
+    fn removal_reason() {
+        let rpc = BitcoinRpc::new("http://127.0.0.1:1".to_owned(), None, None);
+        let mut watcher = MempoolWatcher::new(&rpc);
+
+        let mined: Txid = hash(1);
+        let replaced_in_mempool: Txid = hash(2);
+        let replaced_in_block: Txid = hash(3);
+        let evicted: Txid = hash(4);
+        let replacement: Txid = hash(5);
+        let conflicting: Txid = hash(6);
+
+        watcher.recent.push_back(recent_block(100, &[], &[]));
+        watcher.recent.push_back(recent_block(
+            101,
+            &[mined, conflicting],
+            &[(outpoint(13), conflicting)],
+        ));
+        watcher.spenders.insert(outpoint(12), replacement);
+        // The removed transaction itself is still recorded as the spender of
+        // its own inputs until the removal is processed.
+        watcher.spenders.insert(outpoint(14), evicted);
+
+        let cases = vec![
+            (mined, outpoint(11), RemovalReason::Mined(hash(101))),
+            (
+                replaced_in_mempool,
+                outpoint(12),
+                RemovalReason::Replaced(replacement),
+            ),
+            (
+                replaced_in_block,
+                outpoint(13),
+                RemovalReason::Replaced(conflicting),
+            ),
+            (evicted, outpoint(14), RemovalReason::Evicted),
+        ];
+        for (txid, input, expected) in cases {
+            assert_eq!(
+                watcher.removal_reason(&txid, &[input]),
+                expected,
+                "{}",
+                txid
+            );
+        }
+    }
+}
@@ -0,0 +1,264 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Proofs that transactions are included in a block, as returned by
+//! "gettxoutproof".
+
+use bitcoin::blockdata::block::BlockHeader;
+use bitcoin::network::serialize::deserialize;
+use bitcoin::util::hash::Sha256dHash;
+
+use {u32_from_le, u64_from_le, DecodeError};
+
+/// The most transactions a block can have, its maximum weight over the
+/// minimum weight of a transaction, as bounded by bitcoind.
+const MAX_TRANSACTIONS: u32 = 4_000_000 / 240;
+
+/// A block header and a partial merkle tree of its transactions, as
+/// defined in BIP 37.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MerkleBlock {
+    /// The header of the block
+    pub header: BlockHeader,
+    /// The number of transactions in the block
+    pub total_transactions: u32,
+    /// The hashes of the partial merkle tree, in depth-first order
+    pub hashes: Vec<Sha256dHash>,
+    /// The flag bits of the partial merkle tree, in depth-first order
+    pub flags: Vec<u8>,
+}
+
+impl MerkleBlock {
+    /// Decodes a serialized merkle block.
+    pub fn from_bytes(bytes: &[u8]) -> Result<MerkleBlock, DecodeError> {
+        if bytes.len() < 84 {
+            return Err(DecodeError::Invalid("truncated merkle block"));
+        }
+        let header = deserialize(&bytes[..80])?;
+        let mut reader = Reader {
+            bytes: &bytes[80..],
+        };
+
+        let total_transactions = reader.u32()?;
+        let mut hashes = Vec::new();
+        for _ in 0..reader.compact_size()? {
+            hashes.push(Sha256dHash::from(reader.take(32)?));
+        }
+        let flag_bytes = reader.compact_size()?;
+        let flags = reader.take(flag_bytes)?.to_vec();
+        if !reader.bytes.is_empty() {
+            return Err(DecodeError::Invalid("trailing data after merkle block"));
+        }
+
+        Ok(MerkleBlock {
+            header: header,
+            total_transactions: total_transactions,
+            hashes: hashes,
+            flags: flags,
+        })
+    }
+
+    /// Checks the partial merkle tree against the merkle root of the header
+    /// and returns the txids it proves to be in the block.
+    pub fn extract_matches(&self) -> Result<Vec<Sha256dHash>, DecodeError> {
+        if self.total_transactions == 0 {
+            return Err(DecodeError::Invalid("merkle block without transactions"));
+        }
+        if self.total_transactions > MAX_TRANSACTIONS {
+            return Err(DecodeError::Invalid(
+                "more transactions than fit in a block",
+            ));
+        }
+        if self.hashes.len() > self.total_transactions as usize {
+            return Err(DecodeError::Invalid("more hashes than transactions"));
+        }
+        if self.flags.len() * 8 < self.hashes.len() {
+            return Err(DecodeError::Invalid("fewer flag bits than hashes"));
+        }
+
+        let mut height = 0;
+        while self.width(height) > 1 {
+            height += 1;
+        }
+
+        let mut traversal = Traversal {
+            block: self,
+            bits_used: 0,
+            hashes_used: 0,
+            matches: Vec::new(),
+        };
+        let root = traversal.visit(height, 0)?;
+
+        if (traversal.bits_used + 7) / 8 != self.flags.len()
+            || traversal.hashes_used != self.hashes.len()
+        {
+            return Err(DecodeError::Invalid("unused hashes or flag bits"));
+        }
+        if root != self.header.merkle_root {
+            return Err(DecodeError::Invalid("merkle root mismatch"));
+        }
+
+        Ok(traversal.matches)
+    }
+
+    /// Number of nodes at `height` of the tree, the leaves being at 0.
+    fn width(&self, height: u32) -> u64 {
+        (u64::from(self.total_transactions) + (1 << height) - 1) >> height
+    }
+}
+
+struct Traversal<'a> {
+    block: &'a MerkleBlock,
+    bits_used: usize,
+    hashes_used: usize,
+    matches: Vec<Sha256dHash>,
+}
+
+impl<'a> Traversal<'a> {
+    fn visit(&mut self, height: u32, pos: u64) -> Result<Sha256dHash, DecodeError> {
+        let block = self.block;
+        let flags = &block.flags;
+        if self.bits_used >= flags.len() * 8 {
+            return Err(DecodeError::Invalid("ran out of flag bits"));
+        }
+        let parent_of_match = flags[self.bits_used / 8] & (1 << (self.bits_used % 8)) != 0;
+        self.bits_used += 1;
+
+        if height == 0 || !parent_of_match {
+            let hash = *block
+                .hashes
+                .get(self.hashes_used)
+                .ok_or(DecodeError::Invalid("ran out of hashes"))?;
+            self.hashes_used += 1;
+            if height == 0 && parent_of_match {
+                self.matches.push(hash);
+            }
+            return Ok(hash);
+        }
+
+        let left = self.visit(height - 1, pos * 2)?;
+        let right = if pos * 2 + 1 < block.width(height - 1) {
+            let right = self.visit(height - 1, pos * 2 + 1)?;
+            // Identical siblings allow proving a different set of
+            // transactions (CVE-2012-2459).
+            if right == left {
+                return Err(DecodeError::Invalid("identical sibling hashes"));
+            }
+            right
+        } else {
+            left
+        };
+
+        let mut data = Vec::with_capacity(64);
+        data.extend_from_slice(&left[..]);
+        data.extend_from_slice(&right[..]);
+        Ok(Sha256dHash::from_data(&data))
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: u64) -> Result<&'a [u8], DecodeError> {
+        if (self.bytes.len() as u64) < len {
+            return Err(DecodeError::Invalid("truncated merkle block"));
+        }
+        let (taken, rest) = self.bytes.split_at(len as usize);
+        self.bytes = rest;
+        Ok(taken)
+    }
+
+    fn u32(&mut self) -> Result<u32, DecodeError> {
+        Ok(u32_from_le(self.take(4)?))
+    }
+
+    fn compact_size(&mut self) -> Result<u64, DecodeError> {
+        let len = match self.take(1)?[0] {
+            0xfd => 2,
+            0xfe => 4,
+            0xff => 8,
+            n => return Ok(u64::from(n)),
+        };
+        Ok(u64_from_le(self.take(len)?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::util::hash::Sha256dHash;
+    use bitcoin::util::misc::hex_bytes;
+
+    use super::MerkleBlock;
+    use DecodeError;
+
+    /// "gettxoutproof" of the second transaction of mainnet block 100000.
+    const PROOF: &str = "0100000050120119172a610421a6c3011dd330d9df07b63616c2cc1f1cd002000000\
+                         00006657a9252aacd5c0b2940996ecff952228c3067cc38d4885efb5a4ac4247e9f3\
+                         37221b4d4c86041b0f2b57100400000003876dd0a3ef4a2816ffd1c12ab649825a95\
+                         8b0ff3bb3d6f3e1250f13ddbf0148cc40297f730dd7b5a99567eb8d27b78758f6075\
+                         07c52292d02d4031895b52f2ff49aef42d78e3e9999c9e6ec9e1dddd6cb880bf3b07\
+                         6a03be1318ca789089308e010b";
+
+    fn proof() -> MerkleBlock {
+        MerkleBlock::from_bytes(&hex_bytes(PROOF).unwrap()).unwrap()
+    }
+
+    fn invalid(block: &MerkleBlock) -> &'static str {
+        match block.extract_matches() {
+            Err(DecodeError::Invalid(reason)) => reason,
+            other => panic!("expected an invalid proof, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn extract_matches() {
+        let block = proof();
+        assert_eq!(block.total_transactions, 4);
+        assert_eq!(
+            block.extract_matches().unwrap(),
+            vec![Sha256dHash::from_hex(
+                "fff2525b8931402dd09222c50775608f75787bd2b87e56995a7bdd30f79702c4"
+            )
+            .unwrap(),]
+        );
+    }
+
+    #[test]
+    fn tampered_root() {
+        let mut block = proof();
+        block.hashes[2] = block.hashes[0];
+        assert_eq!(invalid(&block), "merkle root mismatch");
+    }
+
+    #[test]
+    fn identical_siblings() {
+        // Duplicating the last transaction of a block with an odd number
+        // of transactions gives the same root (CVE-2012-2459).
+        let mut block = proof();
+        block.total_transactions = 2;
+        block.hashes = vec![block.hashes[0], block.hashes[0]];
+        block.flags = vec![0b001];
+        assert_eq!(invalid(&block), "identical sibling hashes");
+    }
+
+    #[test]
+    fn trailing_flag_bits() {
+        let mut block = proof();
+        block.flags.push(0);
+        assert_eq!(invalid(&block), "unused hashes or flag bits");
+    }
+
+    #[test]
+    fn too_many_transactions() {
+        let mut block = proof();
+        block.total_transactions = u32::max_value();
+        assert_eq!(invalid(&block), "more transactions than fit in a block");
+    }
+}
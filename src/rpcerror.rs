@@ -0,0 +1,494 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Error codes of the errors returned by bitcoind, from `rpc/protocol.h`,
+//! and the reasons transactions are rejected.
+
+use std::fmt;
+
+/// The RPC whose errors carry a `BroadcastRejection`.
+pub(crate) const SEND_RAW_TRANSACTION: &str = "sendrawtransaction";
+
+macro_rules! error_codes {
+    ($($(#[$attr:meta])* $variant:ident = $code:expr,)*) => {
+        /// The code of an error returned by bitcoind.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum RpcErrorCode {
+            $($(#[$attr])* $variant,)*
+            /// A code not known to this crate
+            Other(i32),
+        }
+
+        impl RpcErrorCode {
+            /// Maps the numeric code of an error.
+            pub fn from_code(code: i32) -> RpcErrorCode {
+                match code {
+                    $(c if c == $code => RpcErrorCode::$variant,)*
+                    code => RpcErrorCode::Other(code),
+                }
+            }
+
+            /// The numeric code of the error.
+            pub fn code(&self) -> i32 {
+                match *self {
+                    $(RpcErrorCode::$variant => $code,)*
+                    RpcErrorCode::Other(code) => code,
+                }
+            }
+        }
+    };
+}
+
+error_codes! {
+    /// The request is not a valid JSON-RPC request
+    InvalidRequest = -32600,
+    /// The method doesn't exist
+    MethodNotFound = -32601,
+    /// The parameters are not valid
+    InvalidParams = -32602,
+    /// Internal JSON-RPC error
+    InternalError = -32603,
+    /// The request couldn't be parsed
+    ParseError = -32700,
+    /// Unclassified error
+    MiscError = -1,
+    /// Unexpected type of a parameter
+    TypeError = -3,
+    /// Invalid address or key, also used when an item is not found
+    InvalidAddressOrKey = -5,
+    /// Out of memory
+    OutOfMemory = -7,
+    /// Invalid, missing or duplicate parameter
+    InvalidParameter = -8,
+    /// Database error
+    DatabaseError = -20,
+    /// Error parsing or validating a structure in raw format
+    DeserializationError = -22,
+    /// General error during transaction or block submission
+    VerifyError = -25,
+    /// Transaction or block rejected by the network rules
+    VerifyRejected = -26,
+    /// Transaction already in the chain
+    VerifyAlreadyInChain = -27,
+    /// Client still warming up
+    InWarmup = -28,
+    /// The method is deprecated
+    MethodDeprecated = -32,
+    /// Bitcoin is not connected
+    ClientNotConnected = -9,
+    /// Still downloading the initial blocks
+    ClientInInitialDownload = -10,
+    /// Node is already added
+    ClientNodeAlreadyAdded = -23,
+    /// Node has not been added before
+    ClientNodeNotAdded = -24,
+    /// Node to disconnect not found in the connected nodes
+    ClientNodeNotConnected = -29,
+    /// Invalid IP or subnet
+    ClientInvalidIpOrSubnet = -30,
+    /// No valid connection manager instance found
+    ClientP2pDisabled = -31,
+    /// Max number of outbound or block-relay connections already open
+    ClientNodeCapacityReached = -34,
+    /// No mempool instance found
+    ClientMempoolDisabled = -33,
+    /// Unspecified problem with the wallet
+    WalletError = -4,
+    /// Not enough funds in the wallet or account
+    WalletInsufficientFunds = -6,
+    /// Invalid label name
+    WalletInvalidLabelName = -11,
+    /// Keypool ran out, call keypoolrefill first
+    WalletKeypoolRanOut = -12,
+    /// Enter the wallet passphrase with walletpassphrase first
+    WalletUnlockNeeded = -13,
+    /// The wallet passphrase entered was incorrect
+    WalletPassphraseIncorrect = -14,
+    /// Command given in wrong wallet encryption state
+    WalletWrongEncState = -15,
+    /// Failed to encrypt the wallet
+    WalletEncryptionFailed = -16,
+    /// Wallet is already unlocked
+    WalletAlreadyUnlocked = -17,
+    /// Invalid wallet specified
+    WalletNotFound = -18,
+    /// No wallet specified, with several wallets loaded
+    WalletNotSpecified = -19,
+    /// This same wallet is already loaded
+    WalletAlreadyLoaded = -35,
+    /// There is already a wallet with the same name
+    WalletAlreadyExists = -36,
+}
+
+impl RpcErrorCode {
+    /// Returns `true` if the node is still starting up; the request can be
+    /// retried later.
+    pub fn is_warmup(&self) -> bool {
+        *self == RpcErrorCode::InWarmup
+    }
+
+    /// Returns `true` if the requested block, transaction or other item
+    /// doesn't exist.
+    pub fn is_not_found(&self) -> bool {
+        *self == RpcErrorCode::InvalidAddressOrKey
+    }
+}
+
+impl fmt::Display for RpcErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.code())
+    }
+}
+
+/// Why "sendrawtransaction" rejected a transaction, parsed from the reject
+/// reason of the error message.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastRejection {
+    /// An input is spent or doesn't exist
+    MissingInputs,
+    /// The transaction is already in the mempool
+    AlreadyInMempool,
+    /// The transaction is already in the chain
+    AlreadyInChain,
+    /// The fee is too low, e.g. to replace a mempool transaction
+    InsufficientFee,
+    /// The fee rate is below the minimum of the full mempool
+    MempoolMinFeeNotMet,
+    /// The fee rate is below the minimum relay fee rate
+    MinRelayFeeNotMet,
+    /// An input is spent by a mempool transaction that can't be replaced
+    MempoolConflict,
+    /// The transaction is non-standard, with the reason, e.g. "dust" or
+    /// "non-mandatory-script-verify-flag (...)"
+    NonStandard(String),
+    /// The fee is above the maximum set by the node or the request
+    MaxFeeExceeded,
+    /// A reject reason not known to this crate, the full message
+    Unknown(String),
+}
+
+/// Reasons of `IsStandardTx` and the standard script checks.
+const NON_STANDARD: &[&str] = &[
+    "version",
+    "tx-size",
+    "tx-size-small",
+    "scriptsig-size",
+    "scriptsig-not-pushonly",
+    "scriptpubkey",
+    "bare-multisig",
+    "dust",
+    "multi-op-return",
+    "bad-txns-nonstandard-inputs",
+    "bad-witness-nonstandard",
+    "non-mandatory-script-verify-flag",
+];
+
+impl BroadcastRejection {
+    /// Parses the message of a "sendrawtransaction" error.
+    pub fn from_message(message: &str) -> BroadcastRejection {
+        // Before 0.19 the reason is prefixed with the numeric reject code,
+        // e.g. "66: min relay fee not met".
+        let reason = match message.find(": ") {
+            Some(i) if i > 0 && message[..i].bytes().all(|b| b.is_ascii_digit()) => {
+                &message[i + 2..]
+            }
+            _ => message,
+        };
+        let starts = |prefix: &str| reason.starts_with(prefix);
+
+        if starts("bad-txns-inputs-missingorspent")
+            || starts("missing-inputs")
+            || starts("Missing inputs")
+        {
+            BroadcastRejection::MissingInputs
+        } else if starts("txn-already-in-mempool") || starts("txn-already-known") {
+            BroadcastRejection::AlreadyInMempool
+        } else if starts("Transaction already in block chain")
+            || starts("Transaction outputs already in utxo set")
+        {
+            BroadcastRejection::AlreadyInChain
+        } else if starts("insufficient fee") {
+            BroadcastRejection::InsufficientFee
+        } else if starts("mempool min fee not met") {
+            BroadcastRejection::MempoolMinFeeNotMet
+        } else if starts("min relay fee not met") {
+            BroadcastRejection::MinRelayFeeNotMet
+        } else if starts("txn-mempool-conflict") {
+            BroadcastRejection::MempoolConflict
+        } else if starts("max-fee-exceeded")
+            || starts("absurdly-high-fee")
+            || starts("Fee exceeds maximum")
+        {
+            BroadcastRejection::MaxFeeExceeded
+        } else if NON_STANDARD.iter().any(|kind| is_reason(reason, kind)) {
+            BroadcastRejection::NonStandard(reason.to_owned())
+        } else {
+            BroadcastRejection::Unknown(message.to_owned())
+        }
+    }
+}
+
+/// Returns `true` if `reason` is `kind`, maybe followed by details.
+fn is_reason(reason: &str, kind: &str) -> bool {
+    reason.starts_with(kind) && {
+        let rest = &reason[kind.len()..];
+        rest.is_empty() || rest.starts_with(' ') || rest.starts_with(',')
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use jsonrpc;
+    use jsonrpc::error::RpcError;
+
+    use super::BroadcastRejection::*;
+    use super::{BroadcastRejection, RpcErrorCode, SEND_RAW_TRANSACTION};
+    use Error;
+
+    fn non_standard(reason: &str) -> BroadcastRejection {
+        NonStandard(reason.to_owned())
+    }
+
+    fn unknown(message: &str) -> BroadcastRejection {
+        Unknown(message.to_owned())
+    }
+
+    #[test]
+    fn from_message() {
+        let cases = vec![
+            // 0.16
+            ("Missing inputs", MissingInputs),
+            ("18: txn-already-in-mempool", AlreadyInMempool),
+            ("Transaction already in block chain", AlreadyInChain),
+            ("66: min relay fee not met", MinRelayFeeNotMet),
+            (
+                "66: mempool min fee not met, 1000 < 1560",
+                MempoolMinFeeNotMet,
+            ),
+            (
+                "66: insufficient fee, rejecting replacement 6e4a...; new feerate \
+                 0.00001000 BTC/kB <= old feerate 0.00002000 BTC/kB",
+                InsufficientFee,
+            ),
+            ("258: txn-mempool-conflict", MempoolConflict),
+            ("256: absurdly-high-fee, 10000000 > 1000000", MaxFeeExceeded),
+            ("64: dust", non_standard("dust")),
+            ("64: scriptpubkey", non_standard("scriptpubkey")),
+            (
+                "64: non-mandatory-script-verify-flag (Signature must be zero for \
+                 failed CHECK(MULTI)SIG operation)",
+                non_standard(
+                    "non-mandatory-script-verify-flag (Signature must be zero for \
+                     failed CHECK(MULTI)SIG operation)",
+                ),
+            ),
+            (
+                "16: mandatory-script-verify-flag-failed (Script evaluated without \
+                 error but finished with a false/empty top stack element)",
+                unknown(
+                    "16: mandatory-script-verify-flag-failed (Script evaluated without \
+                     error but finished with a false/empty top stack element)",
+                ),
+            ),
+            // 0.21
+            ("bad-txns-inputs-missingorspent", MissingInputs),
+            ("txn-already-in-mempool", AlreadyInMempool),
+            ("min relay fee not met, 100 < 141", MinRelayFeeNotMet),
+            ("txn-mempool-conflict", MempoolConflict),
+            (
+                "Fee exceeds maximum configured by -maxtxfee",
+                MaxFeeExceeded,
+            ),
+            ("tx-size-small", non_standard("tx-size-small")),
+            ("version", non_standard("version")),
+            (
+                "bad-txns-nonstandard-inputs",
+                non_standard("bad-txns-nonstandard-inputs"),
+            ),
+            // 28.x
+            ("txn-already-known", AlreadyInMempool),
+            ("Transaction outputs already in utxo set", AlreadyInChain),
+            ("mempool min fee not met, 1000 < 2000", MempoolMinFeeNotMet),
+            (
+                "insufficient fee, rejecting replacement 9d1c...; new feerate \
+                 0.00001000 BTC/kvB <= old feerate 0.00002000 BTC/kvB",
+                InsufficientFee,
+            ),
+            (
+                "Fee exceeds maximum configured by user (e.g. -maxtxfee, maxfeerate)",
+                MaxFeeExceeded,
+            ),
+            ("max-fee-exceeded", MaxFeeExceeded),
+            ("multi-op-return", non_standard("multi-op-return")),
+            (
+                "bad-txns-in-belowout, value in (0.01) < value out (0.02)",
+                unknown("bad-txns-in-belowout, value in (0.01) < value out (0.02)"),
+            ),
+            // Kinds are only matched as whole reasons.
+            ("versions", unknown("versions")),
+        ];
+
+        for (message, rejection) in cases {
+            assert_eq!(
+                BroadcastRejection::from_message(message),
+                rejection,
+                "{}",
+                message
+            );
+        }
+    }
+
+    #[test]
+    fn codes_round_trip() {
+        let cases = vec![
+            (-32600, RpcErrorCode::InvalidRequest),
+            (-32601, RpcErrorCode::MethodNotFound),
+            (-32602, RpcErrorCode::InvalidParams),
+            (-32603, RpcErrorCode::InternalError),
+            (-32700, RpcErrorCode::ParseError),
+            (-1, RpcErrorCode::MiscError),
+            (-3, RpcErrorCode::TypeError),
+            (-4, RpcErrorCode::WalletError),
+            (-5, RpcErrorCode::InvalidAddressOrKey),
+            (-6, RpcErrorCode::WalletInsufficientFunds),
+            (-7, RpcErrorCode::OutOfMemory),
+            (-8, RpcErrorCode::InvalidParameter),
+            (-9, RpcErrorCode::ClientNotConnected),
+            (-10, RpcErrorCode::ClientInInitialDownload),
+            (-11, RpcErrorCode::WalletInvalidLabelName),
+            (-12, RpcErrorCode::WalletKeypoolRanOut),
+            (-13, RpcErrorCode::WalletUnlockNeeded),
+            (-14, RpcErrorCode::WalletPassphraseIncorrect),
+            (-15, RpcErrorCode::WalletWrongEncState),
+            (-16, RpcErrorCode::WalletEncryptionFailed),
+            (-17, RpcErrorCode::WalletAlreadyUnlocked),
+            (-18, RpcErrorCode::WalletNotFound),
+            (-19, RpcErrorCode::WalletNotSpecified),
+            (-20, RpcErrorCode::DatabaseError),
+            (-22, RpcErrorCode::DeserializationError),
+            (-23, RpcErrorCode::ClientNodeAlreadyAdded),
+            (-24, RpcErrorCode::ClientNodeNotAdded),
+            (-25, RpcErrorCode::VerifyError),
+            (-26, RpcErrorCode::VerifyRejected),
+            (-27, RpcErrorCode::VerifyAlreadyInChain),
+            (-28, RpcErrorCode::InWarmup),
+            (-29, RpcErrorCode::ClientNodeNotConnected),
+            (-30, RpcErrorCode::ClientInvalidIpOrSubnet),
+            (-31, RpcErrorCode::ClientP2pDisabled),
+            (-32, RpcErrorCode::MethodDeprecated),
+            (-33, RpcErrorCode::ClientMempoolDisabled),
+            (-34, RpcErrorCode::ClientNodeCapacityReached),
+            (-35, RpcErrorCode::WalletAlreadyLoaded),
+            (-36, RpcErrorCode::WalletAlreadyExists),
+            // Unassigned codes
+            (0, RpcErrorCode::Other(0)),
+            (-2, RpcErrorCode::Other(-2)),
+            (-21, RpcErrorCode::Other(-21)),
+            (-37, RpcErrorCode::Other(-37)),
+            (-32000, RpcErrorCode::Other(-32000)),
+            (i32::min_value(), RpcErrorCode::Other(i32::min_value())),
+        ];
+
+        for (code, error_code) in cases {
+            assert_eq!(RpcErrorCode::from_code(code), error_code, "{}", code);
+            assert_eq!(error_code.code(), code, "{:?}", error_code);
+            assert_eq!(error_code.to_string(), code.to_string());
+        }
+    }
+
+    fn server_error(rpc_name: &'static str, code: i32, message: &str) -> Error {
+        let err = RpcError {
+            code: code,
+            message: message.to_owned(),
+            data: None,
+        };
+        Error::from((rpc_name, jsonrpc::Error::Rpc(err)))
+    }
+
+    #[test]
+    fn server_errors() {
+        // (rpc, code, message, not found, warmup, rejection)
+        let cases = vec![
+            ("getblock", -5, "Block not found", true, false, None),
+            (
+                "getblockcount",
+                -28,
+                "Loading block index...",
+                false,
+                true,
+                None,
+            ),
+            (
+                "getblock",
+                -8,
+                "blockhash must be of length 64",
+                false,
+                false,
+                None,
+            ),
+            (
+                SEND_RAW_TRANSACTION,
+                -26,
+                "min relay fee not met, 100 < 141",
+                false,
+                false,
+                Some(MinRelayFeeNotMet),
+            ),
+            (
+                SEND_RAW_TRANSACTION,
+                -25,
+                "bad-txns-inputs-missingorspent",
+                false,
+                false,
+                Some(MissingInputs),
+            ),
+            (
+                SEND_RAW_TRANSACTION,
+                -27,
+                "Transaction already in block chain",
+                false,
+                false,
+                Some(AlreadyInChain),
+            ),
+            // Not found, but not a rejection
+            (
+                SEND_RAW_TRANSACTION,
+                -5,
+                "No such mempool or blockchain transaction",
+                true,
+                false,
+                None,
+            ),
+            // Rejection codes of other RPCs aren't rejections
+            ("testmempoolaccept", -26, "dust", false, false, None),
+            ("getblock", -32000, "unknown", false, false, None),
+        ];
+
+        for (rpc_name, code, message, not_found, warmup, rejection) in cases {
+            let err = server_error(rpc_name, code, message);
+            match err {
+                Error::Server {
+                    rpc_name: name,
+                    code: error_code,
+                    message: ref msg,
+                    ref data,
+                } => {
+                    assert_eq!(name, rpc_name);
+                    assert_eq!(error_code, RpcErrorCode::from_code(code));
+                    assert_eq!(msg, message);
+                    assert!(data.is_none());
+                }
+                ref other => panic!("unexpected error {:?}", other),
+            }
+            assert_eq!(err.code(), Some(RpcErrorCode::from_code(code)));
+            assert_eq!(err.is_not_found(), not_found, "{}", message);
+            assert_eq!(err.is_warmup(), warmup, "{}", message);
+            assert_eq!(err.rejection(), rejection, "{}", message);
+        }
+    }
+}
@@ -0,0 +1,470 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Client-side block statistics, similar to "getblockstats".
+
+use std::collections::{HashMap, HashSet};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxOut};
+use bitcoin::network::serialize::{self, serialize};
+use bitcoin::util::hash::Sha256dHash;
+use bitcoin::util::misc::hex_bytes;
+
+use crossbeam;
+
+use blockchain::TransactionsWithPrevouts;
+use hashes::{BlockHash, Txid};
+use {bulk, malformed, BitcoinRpc, RpcResult};
+
+/// Number of transactions fetched concurrently when the node doesn't
+/// return prevouts with the block.
+const CONCURRENCY: usize = 8;
+
+/// Statistics of a block.
+///
+/// Like "getblockstats", the coinbase transaction is not counted in `ins`
+/// and is ignored for fees and fee rates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockStats {
+    /// Hash of the block
+    pub hash: BlockHash,
+    /// Number of transactions, including the coinbase
+    pub txs: u64,
+    /// Number of inputs, excluding the coinbase
+    pub ins: u64,
+    /// Number of outputs
+    pub outs: u64,
+    /// Serialized size of the block
+    pub total_size: u64,
+    /// Weight of the block as defined in BIP 141
+    pub total_weight: u64,
+    /// Sum of all fees in satoshis
+    pub total_fee: u64,
+    /// Fee rates at the 10th, 25th, 50th, 75th and 90th percentile weight
+    /// unit, in sat/vB
+    pub feerate_percentiles: [u64; 5],
+    /// Minimum fee rate in sat/vB
+    pub min_feerate: u64,
+    /// Maximum fee rate in sat/vB
+    pub max_feerate: u64,
+    /// Average fee rate in sat/vB
+    pub avg_feerate: u64,
+    /// Number of transactions with witness data
+    pub segwit_txs: u64,
+}
+
+impl BlockStats {
+    /// Share of the non-coinbase transactions that have witness data.
+    pub fn segwit_share(&self) -> f64 {
+        if self.txs <= 1 {
+            0.0
+        } else {
+            self.segwit_txs as f64 / (self.txs - 1) as f64
+        }
+    }
+}
+
+/// Computes the statistics of `block` with hash `hash`.
+///
+/// `prevouts` must contain the outputs spent by the block that are not
+/// created in the block itself, otherwise `Error::MissingPrevout` is
+/// returned.
+pub fn block_stats(
+    block: &Block,
+    hash: BlockHash,
+    prevouts: &HashMap<OutPoint, TxOut>,
+) -> StatsResult<BlockStats> {
+    let mut created = HashMap::new();
+    let mut stats = BlockStats {
+        hash: hash,
+        txs: block.txdata.len() as u64,
+        ins: 0,
+        outs: 0,
+        total_size: serialize(block)?.len() as u64,
+        total_weight: 0,
+        total_fee: 0,
+        feerate_percentiles: [0; 5],
+        min_feerate: 0,
+        max_feerate: 0,
+        avg_feerate: 0,
+        segwit_txs: 0,
+    };
+
+    let mut stripped_size = stats.total_size;
+    // (fee rate, weight) of every non-coinbase transaction
+    let mut feerates = Vec::with_capacity(block.txdata.len());
+
+    for (i, tx) in block.txdata.iter().enumerate() {
+        let (size, tx_stripped_size) = tx_sizes(tx)?;
+        let weight = tx_stripped_size * 3 + size;
+        stripped_size -= size - tx_stripped_size;
+        stats.outs += tx.output.len() as u64;
+
+        let txid = tx.txid();
+        for (vout, output) in tx.output.iter().enumerate() {
+            let outpoint = OutPoint {
+                txid: txid,
+                vout: vout as u32,
+            };
+            created.insert(outpoint, output.value);
+        }
+
+        if i == 0 {
+            continue;
+        }
+
+        if tx.input.iter().any(|input| !input.witness.is_empty()) {
+            stats.segwit_txs += 1;
+        }
+        stats.ins += tx.input.len() as u64;
+
+        let mut in_value = 0;
+        for input in &tx.input {
+            let outpoint = input.previous_output;
+            in_value += created
+                .get(&outpoint)
+                .cloned()
+                .or_else(|| prevouts.get(&outpoint).map(|prevout| prevout.value))
+                .ok_or(Error::MissingPrevout { outpoint: outpoint })?;
+        }
+        let out_value: u64 = tx.output.iter().map(|o| o.value).sum();
+        let fee = in_value
+            .checked_sub(out_value)
+            .ok_or_else(|| Error::NegativeFee { txid: txid.into() })?;
+
+        stats.total_fee += fee;
+        feerates.push((fee / vsize(weight), weight));
+    }
+
+    stats.total_weight = stripped_size * 3 + stats.total_size;
+
+    if !feerates.is_empty() {
+        let total_tx_weight: u64 = feerates.iter().map(|&(_, weight)| weight).sum();
+        feerates.sort();
+
+        stats.min_feerate = feerates[0].0;
+        stats.max_feerate = feerates[feerates.len() - 1].0;
+        stats.avg_feerate = stats.total_fee / vsize(total_tx_weight);
+        stats.feerate_percentiles = feerate_percentiles(&feerates, total_tx_weight);
+    }
+
+    Ok(stats)
+}
+
+/// Fetches the outputs spent by `block` with hash `hash` that are not
+/// created in the block itself.
+///
+/// They are taken from "getblock" with verbosity 3 (23.0+). Older nodes
+/// fall back to fetching every transaction spent by the block through
+/// "getrawtransaction", several at a time, which requires `txindex` for
+/// transactions that are neither in the mempool nor in the wallet.
+pub fn fetch_prevouts(
+    rpc: &BitcoinRpc,
+    hash: &BlockHash,
+    block: &Block,
+) -> RpcResult<HashMap<OutPoint, TxOut>> {
+    match prevouts_from_block_info(rpc, hash) {
+        Ok(Some(prevouts)) => return Ok(prevouts),
+        // Verbosity 3 was ignored or refused.
+        Ok(None) => {}
+        Err(ref e) if e.code().is_some() => {}
+        Err(e) => return Err(e),
+    }
+
+    let in_block: HashSet<Sha256dHash> = block.txdata.iter().map(|tx| tx.txid()).collect();
+
+    let mut txids = HashSet::new();
+    for tx in block.txdata.iter().skip(1) {
+        for input in &tx.input {
+            if !in_block.contains(&input.previous_output.txid) {
+                txids.insert(Txid::from(input.previous_output.txid));
+            }
+        }
+    }
+    let txids: Vec<Txid> = txids.into_iter().collect();
+    let txs: Vec<Transaction> = bulk::get_many(rpc, &txids, CONCURRENCY)?;
+
+    let mut prevouts = HashMap::new();
+    for (txid, tx) in txids.into_iter().zip(txs) {
+        for (vout, output) in tx.output.into_iter().enumerate() {
+            let outpoint = OutPoint {
+                txid: txid.into_hash(),
+                vout: vout as u32,
+            };
+            prevouts.insert(outpoint, output);
+        }
+    }
+
+    Ok(prevouts)
+}
+
+/// The outputs spent by the block from "getblock" with verbosity 3, or
+/// `None` if the node left them out.
+fn prevouts_from_block_info(
+    rpc: &BitcoinRpc,
+    hash: &BlockHash,
+) -> RpcResult<Option<HashMap<OutPoint, TxOut>>> {
+    let info = rpc.get_block_info::<TransactionsWithPrevouts>(hash)?;
+
+    let mut prevouts = HashMap::new();
+    for input in info.tx.iter().skip(1).flat_map(|tx| &tx.vin) {
+        let (txid, vout, prevout) = match (input.txid, input.vout, input.prevout.as_ref()) {
+            (Some(txid), Some(vout), Some(prevout)) => (txid, vout, prevout),
+            _ => return Ok(None),
+        };
+
+        let hex = &prevout.script_pub_key.hex;
+        let script = hex_bytes(hex)
+            .map_err(|e| malformed("getblock", "tx.vin.prevout.scriptPubKey.hex", hex, e))?;
+        let output = TxOut {
+            value: prevout.value.as_sat() as u64,
+            script_pubkey: Script::from(script),
+        };
+        let outpoint = OutPoint {
+            txid: txid.into_hash(),
+            vout: vout,
+        };
+        prevouts.insert(outpoint, output);
+    }
+
+    Ok(Some(prevouts))
+}
+
+/// Fetches the block with hash `hash` and its prevouts and computes its
+/// statistics.
+pub fn get_block_stats(rpc: &BitcoinRpc, hash: &BlockHash) -> StatsResult<BlockStats> {
+    let block: Block = rpc.get(hash)?;
+    let prevouts = fetch_prevouts(rpc, hash, &block)?;
+
+    block_stats(&block, *hash, &prevouts)
+}
+
+/// Computes the statistics of the blocks from `start` to `end` (inclusive),
+/// fetching blocks and prevouts from `threads` threads.
+pub fn get_block_stats_range(
+    rpc: &BitcoinRpc,
+    start: u64,
+    end: u64,
+    threads: usize,
+) -> StatsResult<Vec<BlockStats>> {
+    let threads = threads.max(1);
+
+    crossbeam::scope(|scope| {
+        // Thread `i` takes every `threads`th height from `start + i`.
+        let handles: Vec<_> = (0..threads)
+            .map(|i| {
+                scope.spawn(move || {
+                    (start..=end)
+                        .skip(i)
+                        .step_by(threads)
+                        .map(|height| get_block_stats(rpc, &rpc.get_blockhash(height)?))
+                        .collect::<StatsResult<Vec<BlockStats>>>()
+                })
+            })
+            .collect();
+
+        let mut per_thread = Vec::with_capacity(threads);
+        for handle in handles {
+            per_thread.push(handle.join()?.into_iter());
+        }
+
+        // Taking one from each thread in turn restores the height order;
+        // the first thread to run out is followed only by ones that did too.
+        let mut stats = Vec::new();
+        'heights: loop {
+            for thread_stats in &mut per_thread {
+                match thread_stats.next() {
+                    Some(next) => stats.push(next),
+                    None => break 'heights,
+                }
+            }
+        }
+
+        Ok(stats)
+    })
+}
+
+/// Returns the serialized size of `tx` with and without witness data.
+fn tx_sizes(tx: &Transaction) -> StatsResult<(u64, u64)> {
+    let size = serialize(tx)?.len() as u64;
+
+    let mut stripped = tx.clone();
+    for input in &mut stripped.input {
+        input.witness.clear();
+    }
+    let stripped_size = serialize(&stripped)?.len() as u64;
+
+    Ok((size, stripped_size))
+}
+
+fn vsize(weight: u64) -> u64 {
+    ((weight + 3) / 4).max(1)
+}
+
+/// Weighted percentiles the way "getblockstats" computes them. `feerates`
+/// must be sorted.
+fn feerate_percentiles(feerates: &[(u64, u64)], total_weight: u64) -> [u64; 5] {
+    let thresholds = [
+        total_weight / 10,
+        total_weight / 4,
+        total_weight / 2,
+        total_weight * 3 / 4,
+        total_weight * 9 / 10,
+    ];
+
+    let mut result = [0; 5];
+    let mut next = 0;
+    let mut cumulative = 0;
+    for &(feerate, weight) in feerates {
+        cumulative += weight;
+        while next < 5 && cumulative >= thresholds[next] {
+            result[next] = feerate;
+            next += 1;
+        }
+    }
+    for percentile in result.iter_mut().skip(next) {
+        *percentile = feerates[feerates.len() - 1].0;
+    }
+
+    result
+}
+
+pub type StatsResult<T> = Result<T, Error>;
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Rpc { err: e }
+    }
+}
+
+impl From<serialize::Error> for Error {
+    fn from(e: serialize::Error) -> Error {
+        Error::Encode { err: e }
+    }
+}
+
+/// The block statistics error type
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Fetching the block or its prevouts failed.
+    #[fail(display = "failed to fetch the block or its prevouts")]
+    Rpc {
+        #[cause]
+        err: ::Error,
+    },
+    /// An output spent by the block is neither created in the block nor
+    /// among the given prevouts.
+    #[fail(display = "prevout {:?} spent by the block is missing", outpoint)]
+    MissingPrevout { outpoint: OutPoint },
+    /// A transaction pays more than its prevouts are worth, i.e. the
+    /// prevouts don't match the block.
+    #[fail(display = "transaction {} pays more than it spends", txid)]
+    NegativeFee { txid: Txid },
+    /// The block could not be serialized to measure it.
+    #[fail(display = "failed to serialize the block")]
+    Encode {
+        #[cause]
+        err: serialize::Error,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use test_utils::fixed_block;
+
+    use super::*;
+
+    fn outpoint(txid: Sha256dHash, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: txid,
+            vout: vout,
+        }
+    }
+
+    fn prevouts(external: &[OutPoint], values: &[u64]) -> HashMap<OutPoint, TxOut> {
+        external
+            .iter()
+            .zip(values)
+            .map(|(outpoint, &value)| {
+                let output = TxOut {
+                    value: value,
+                    script_pubkey: Script::new(),
+                };
+                (*outpoint, output)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fixed_block_stats() {
+        let external = [
+            outpoint(Sha256dHash::from_data(&[1]), 0),
+            outpoint(Sha256dHash::from_data(&[2]), 3),
+        ];
+        let block = fixed_block(&external);
+        let hash = BlockHash::from(Sha256dHash::from_data(&[3]));
+
+        let stats = block_stats(&block, hash, &prevouts(&external, &[100_000, 50_000])).unwrap();
+        assert_eq!(
+            stats,
+            BlockStats {
+                hash: hash,
+                txs: 4,
+                ins: 3,
+                outs: 4,
+                // The header, the transaction count and the transactions
+                total_size: 80 + 1 + 61 + 61 + 75 + 61,
+                total_weight: (80 + 1 + 61 + 61 + 61 + 61) * 3 + 339,
+                total_fee: 6510,
+                // Rounded down to whole sat/vB: 0, 16 and 84 sat/vB for
+                // 244, 244 and 258 weight units.
+                feerate_percentiles: [0, 0, 16, 84, 84],
+                min_feerate: 0,
+                max_feerate: 84,
+                // 6510 sat for 187 vbytes
+                avg_feerate: 34,
+                segwit_txs: 1,
+            }
+        );
+        assert!((stats.segwit_share() - 1.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn missing_prevout() {
+        let external = [
+            outpoint(Sha256dHash::from_data(&[1]), 0),
+            outpoint(Sha256dHash::from_data(&[2]), 3),
+        ];
+        let block = fixed_block(&external);
+        let hash = BlockHash::from(Sha256dHash::from_data(&[3]));
+
+        match block_stats(&block, hash, &prevouts(&external[..1], &[100_000])) {
+            Err(Error::MissingPrevout { outpoint }) => assert_eq!(outpoint, external[1]),
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+
+    #[test]
+    fn negative_fee() {
+        let external = [
+            outpoint(Sha256dHash::from_data(&[1]), 0),
+            outpoint(Sha256dHash::from_data(&[2]), 3),
+        ];
+        let block = fixed_block(&external);
+        let hash = BlockHash::from(Sha256dHash::from_data(&[3]));
+
+        match block_stats(&block, hash, &prevouts(&external, &[100_000, 40_000])) {
+            Err(Error::NegativeFee { txid }) => {
+                assert_eq!(txid, Txid::from(block.txdata[3].txid()))
+            }
+            other => panic!("unexpected result {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Blocks and transactions shared by the tests.
+
+use bitcoin::blockdata::block::{Block, BlockHeader};
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::{OutPoint, Transaction, TxIn, TxOut};
+use bitcoin::util::hash::Sha256dHash;
+
+/// The script `OP_TRUE`.
+pub fn op_true() -> Script {
+    Script::from(vec![0x51])
+}
+
+/// A transaction spending `spends` with empty scripts and `witness`, and
+/// paying `pays` as (script, value) pairs.
+pub fn tx(spends: &[OutPoint], witness: Vec<Vec<u8>>, pays: &[(&Script, u64)]) -> Transaction {
+    Transaction {
+        version: 2,
+        lock_time: 0,
+        input: spends
+            .iter()
+            .map(|outpoint| TxIn {
+                previous_output: *outpoint,
+                script_sig: Script::new(),
+                sequence: 0xffff_ffff,
+                witness: witness.clone(),
+            })
+            .collect(),
+        output: pays
+            .iter()
+            .map(|&(script, value)| TxOut {
+                value: value,
+                script_pubkey: script.clone(),
+            })
+            .collect(),
+    }
+}
+
+/// A header on top of `prev`, with an arbitrary merkle root and no work.
+pub fn header(prev: Sha256dHash, nonce: u32) -> BlockHeader {
+    BlockHeader {
+        version: 1,
+        prev_blockhash: prev,
+        merkle_root: Sha256dHash::from_data(&[]),
+        time: 0,
+        bits: 0,
+        nonce: nonce,
+    }
+}
+
+/// A block of `txdata` with an arbitrary header.
+pub fn block(txdata: Vec<Transaction>) -> Block {
+    Block {
+        header: header(Sha256dHash::from_data(&[]), 0),
+        txdata: txdata,
+    }
+}
+
+/// A block with a coinbase and three transactions paying to `OP_TRUE`:
+///
+/// - paying a fee of 1000 sat for 61 vbytes, spending `external[0]`
+/// - paying a fee of 5500 sat for 65 vbytes (75 bytes, 258 weight
+///   units), with witness data, spending the previous one
+/// - paying a fee of 10 sat for 61 vbytes, spending `external[1]`
+///
+/// given that `external` pays 100000 and 50000 sat.
+pub fn fixed_block(external: &[OutPoint]) -> Block {
+    let script = op_true();
+    let coinbase = tx(
+        &[OutPoint {
+            txid: Sha256dHash::from_data(&[]),
+            vout: 0xffff_ffff,
+        }],
+        vec![],
+        &[(&script, 5_000_000_000)],
+    );
+    let tx1 = tx(&external[..1], vec![], &[(&script, 99_000)]);
+    let spent = OutPoint {
+        txid: tx1.txid(),
+        vout: 0,
+    };
+    let tx2 = tx(&[spent], vec![vec![0x01; 10]], &[(&script, 93_500)]);
+    let tx3 = tx(&external[1..], vec![], &[(&script, 49_990)]);
+
+    block(vec![coinbase, tx1, tx2, tx3])
+}
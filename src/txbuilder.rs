@@ -0,0 +1,280 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! Building, funding, signing and broadcasting transactions.
+
+use bitcoin::blockdata::transaction::{OutPoint, Transaction};
+use bitcoin::network::serialize::{deserialize, serialize};
+use bitcoin::util::address::Address;
+use bitcoin::util::misc::hex_bytes;
+
+use serde_json::value::{Map, Value};
+
+use amount::Amount;
+use blockchain::FundRawTransactionOptions;
+use hashes::Txid;
+use rpcerror::SEND_RAW_TRANSACTION;
+use {bytes_to_hex, malformed, u32_from_le, BitcoinRpc, Error, RpcResult};
+
+/// An output of a transaction being built.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TxOutput {
+    /// Pay the amount to the address
+    Pay(Address, Amount),
+    /// An OP_RETURN output carrying the data
+    Data(Vec<u8>),
+}
+
+/// A transaction funded by the wallet.
+#[derive(Debug, Clone)]
+pub struct FundedTransaction {
+    /// The funded, unsigned transaction
+    pub tx: Transaction,
+    /// Fee the transaction pays
+    pub fee: Amount,
+    /// Position of the change output, if one was added
+    pub change_position: Option<usize>,
+}
+
+/// Builds a transaction whose outputs keep the order they were added in.
+///
+/// [`send`][] runs the whole pipeline; [`create`][], [`fund`][],
+/// [`sign`][] and [`broadcast`][] run a single step.
+///
+/// [`send`]: #method.send
+/// [`create`]: #method.create
+/// [`fund`]: fn.fund.html
+/// [`sign`]: fn.sign.html
+/// [`broadcast`]: fn.broadcast.html
+#[derive(Debug, Clone, Default)]
+pub struct TransactionBuilder {
+    inputs: Vec<OutPoint>,
+    outputs: Vec<TxOutput>,
+    locktime: Option<u32>,
+    replaceable: Option<bool>,
+    options: FundRawTransactionOptions,
+}
+
+impl TransactionBuilder {
+    /// Creates a builder without inputs or outputs.
+    pub fn new() -> Self {
+        TransactionBuilder::default()
+    }
+
+    /// Spends `outpoint`. More inputs are added when funding if needed.
+    pub fn input(mut self, outpoint: OutPoint) -> Self {
+        self.inputs.push(outpoint);
+        self
+    }
+
+    /// Pays `amount` to `address`.
+    pub fn pay_to(mut self, address: Address, amount: Amount) -> Self {
+        self.outputs.push(TxOutput::Pay(address, amount));
+        self
+    }
+
+    /// Adds an OP_RETURN output carrying `data`.
+    pub fn data(mut self, data: Vec<u8>) -> Self {
+        self.outputs.push(TxOutput::Data(data));
+        self
+    }
+
+    /// Sets the locktime.
+    pub fn locktime(mut self, locktime: u32) -> Self {
+        self.locktime = Some(locktime);
+        self
+    }
+
+    /// Signals BIP125 replaceability.
+    pub fn replaceable(mut self, replaceable: bool) -> Self {
+        self.replaceable = Some(replaceable);
+        self.options.replaceable = Some(replaceable);
+        self
+    }
+
+    /// Sets the options used when funding.
+    pub fn fund_options(mut self, options: FundRawTransactionOptions) -> Self {
+        self.options = options;
+        if self.replaceable.is_some() {
+            self.options.replaceable = self.replaceable;
+        }
+        self
+    }
+
+    /// Creates the unfunded transaction through "createrawtransaction".
+    /// Fails with `Error::WrongNetwork` if an address isn't valid on the
+    /// network of the node.
+    pub fn create(&self, rpc: &BitcoinRpc) -> RpcResult<Transaction> {
+        let hex = self.create_hex(rpc)?;
+        decode_unfunded_tx("createrawtransaction", &hex)
+    }
+
+    fn create_hex(&self, rpc: &BitcoinRpc) -> RpcResult<String> {
+        let inputs: Vec<Value> = self
+            .inputs
+            .iter()
+            .map(|o| {
+                let mut object = Map::new();
+                object.insert("txid".into(), o.txid.to_string().into());
+                object.insert("vout".into(), o.vout.into());
+                Value::Object(object)
+            })
+            .collect();
+
+        let mut outputs: Vec<Value> = Vec::with_capacity(self.outputs.len());
+        for output in &self.outputs {
+            let mut object = Map::new();
+            match *output {
+                TxOutput::Pay(ref address, amount) => {
+                    rpc.check_address(address)?;
+                    object.insert(address.to_string(), amount.to_string().into())
+                }
+                TxOutput::Data(ref data) => object.insert("data".into(), bytes_to_hex(data).into()),
+            };
+            outputs.push(Value::Object(object));
+        }
+
+        let mut params = vec![inputs.into(), outputs.into()];
+        if self.locktime.is_some() || self.replaceable.is_some() {
+            params.push(self.locktime.unwrap_or(0).into());
+        }
+        if let Some(replaceable) = self.replaceable {
+            params.push(replaceable.into());
+        }
+
+        rpc.do_rpc("createrawtransaction", &params)
+    }
+
+    /// Creates, funds, signs and broadcasts the transaction, returning it
+    /// and its txid.
+    pub fn send(&self, rpc: &BitcoinRpc) -> RpcResult<(Transaction, Txid)> {
+        // The unfunded transaction goes to the node as is, it's decoded only
+        // once it has inputs.
+        let funded = fund_hex(rpc, self.create_hex(rpc)?, &self.options)?;
+        let signed = sign(rpc, &funded.tx)?;
+        let txid = broadcast(rpc, &signed)?;
+
+        Ok((signed, txid))
+    }
+}
+
+/// Funds `tx` with inputs of the wallet through "fundrawtransaction".
+pub fn fund(
+    rpc: &BitcoinRpc,
+    tx: &Transaction,
+    options: &FundRawTransactionOptions,
+) -> RpcResult<FundedTransaction> {
+    fund_hex(rpc, encode_tx("fundrawtransaction", tx)?, options)
+}
+
+fn fund_hex(
+    rpc: &BitcoinRpc,
+    hex: String,
+    options: &FundRawTransactionOptions,
+) -> RpcResult<FundedTransaction> {
+    let rpc_name = "fundrawtransaction";
+    let result = rpc.fund_raw_transaction(hex, options)?;
+
+    Ok(FundedTransaction {
+        tx: decode_tx(rpc_name, &result.hex)?,
+        fee: result.fee,
+        change_position: if result.changepos < 0 {
+            None
+        } else {
+            Some(result.changepos as usize)
+        },
+    })
+}
+
+/// Signs `tx` with the keys of the wallet through
+/// "signrawtransactionwithwallet". Fails if not every input could be signed.
+pub fn sign(rpc: &BitcoinRpc, tx: &Transaction) -> RpcResult<Transaction> {
+    let rpc_name = "signrawtransactionwithwallet";
+    let signed = rpc.sign_raw_transaction_with_wallet(encode_tx(rpc_name, tx)?)?;
+    if !signed.complete {
+        return Err(Error::IncompleteSignature);
+    }
+
+    decode_tx(rpc_name, &signed.hex)
+}
+
+/// Broadcasts `tx` through "sendrawtransaction", returning its txid.
+///
+/// If the node refuses the transaction, [`Error::rejection`][] tells why.
+///
+/// [`Error::rejection`]: ../enum.Error.html#method.rejection
+pub fn broadcast(rpc: &BitcoinRpc, tx: &Transaction) -> RpcResult<Txid> {
+    let rpc_name = SEND_RAW_TRANSACTION;
+    rpc.do_rpc(rpc_name, &[encode_tx(rpc_name, tx)?.into()])
+}
+
+fn encode_tx(rpc_name: &'static str, tx: &Transaction) -> RpcResult<String> {
+    Ok(bytes_to_hex(&serialize(tx).map_err(|e| (rpc_name, e))?))
+}
+
+fn decode_tx(rpc_name: &'static str, hex: &str) -> RpcResult<Transaction> {
+    let bytes = hex_bytes(hex).map_err(|e| malformed(rpc_name, ".", hex, e))?;
+    deserialize(&bytes).map_err(|e| malformed(rpc_name, ".", hex, e))
+}
+
+/// Decodes a transaction without witness data, which may have no inputs.
+///
+/// The decoder of rust-bitcoin reads an empty input list as the segwit
+/// marker, so such transactions are decoded field by field.
+fn decode_unfunded_tx(rpc_name: &'static str, hex: &str) -> RpcResult<Transaction> {
+    let bytes = hex_bytes(hex).map_err(|e| malformed(rpc_name, ".", hex, e))?;
+    if bytes.len() < 10 || bytes[4] != 0 {
+        return deserialize(&bytes).map_err(|e| malformed(rpc_name, ".", hex, e));
+    }
+
+    let locktime_at = bytes.len() - 4;
+    Ok(Transaction {
+        version: u32_from_le(&bytes[..4]),
+        lock_time: u32_from_le(&bytes[locktime_at..]),
+        input: Vec::new(),
+        output: deserialize(&bytes[5..locktime_at])
+            .map_err(|e| malformed(rpc_name, ".", hex, e))?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::script::Script;
+
+    use super::*;
+
+    #[test]
+    fn decode_zero_input_tx() {
+        // As returned by "createrawtransaction" with no inputs and an output
+        // of 0.1 BTC to a P2WPKH script.
+        let hex =
+            "0200000000018096980000000000160014751e76e8199196d454941c45d1b3a323f1433bd600000000";
+
+        let tx = decode_unfunded_tx("createrawtransaction", hex).unwrap();
+        assert_eq!(tx.version, 2);
+        assert_eq!(tx.lock_time, 0);
+        assert!(tx.input.is_empty());
+        assert_eq!(tx.output.len(), 1);
+        assert_eq!(tx.output[0].value, 10_000_000);
+        let script = hex_bytes("0014751e76e8199196d454941c45d1b3a323f1433bd6").unwrap();
+        assert_eq!(tx.output[0].script_pubkey, Script::from(script));
+
+        assert_eq!(encode_tx("fundrawtransaction", &tx).unwrap(), hex);
+    }
+
+    #[test]
+    fn decode_unfunded_tx_with_inputs() {
+        let hex = "0200000001b8a1d1e0a5e1e2f2b1c1d1e1f1011121314151617181910111213141516171a00000000000fdffffff018096980000000000160014751e76e8199196d454941c45d1b3a323f1433bd600000000";
+
+        let tx = decode_unfunded_tx("createrawtransaction", hex).unwrap();
+        assert_eq!(tx.input.len(), 1);
+        assert_eq!(tx.input[0].previous_output.vout, 0);
+        assert_eq!(tx.input[0].sequence, 0xffff_fffd);
+        assert_eq!(tx.output[0].value, 10_000_000);
+    }
+}
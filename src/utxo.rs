@@ -0,0 +1,473 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! UTXO tracking for a set of watched scripts.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fs::{self, File};
+use std::io;
+use std::path::Path;
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::script::Script;
+use bitcoin::blockdata::transaction::OutPoint;
+use bitcoin::util::address::Address;
+use bitcoin::util::hash::Sha256dHash;
+use bitcoin::util::misc::hex_bytes;
+
+use serde_json;
+
+use blockchain::BlockRef;
+use follower::{ChainFollower, Event};
+use hashes::BlockHash;
+use {bytes_to_hex, BitcoinRpc};
+
+/// Number of blocks that can be reverted on a reorg.
+const MAX_REORG_DEPTH: usize = 100;
+
+/// An unspent output paying to a watched script.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Utxo {
+    /// The output
+    pub outpoint: OutPoint,
+    /// Value in satoshis
+    pub value: u64,
+    /// The watched script
+    pub script_pubkey: Script,
+    /// Height of the block that created the output
+    pub height: u64,
+}
+
+/// Changes made by a connected block, used to revert it.
+#[derive(Debug, Clone)]
+struct BlockUndo {
+    hash: BlockHash,
+    created: Vec<OutPoint>,
+    spent: Vec<Utxo>,
+}
+
+/// Keeps the UTXO set of a set of scripts by following the active chain.
+///
+/// Only full blocks are looked at, so neither a wallet nor `txindex` is
+/// needed. The state can be saved and loaded to resume after a restart.
+pub struct UtxoTracker {
+    scripts: HashSet<Script>,
+    tip: BlockRef,
+    utxos: HashMap<OutPoint, Utxo>,
+    undo: VecDeque<BlockUndo>,
+}
+
+impl UtxoTracker {
+    /// Creates an empty tracker. Blocks after `height`/`hash` are scanned,
+    /// so it must be older than the first output paying to `scripts`.
+    pub fn new(scripts: Vec<Script>, height: u64, hash: BlockHash) -> Self {
+        UtxoTracker {
+            scripts: scripts.into_iter().collect(),
+            tip: BlockRef {
+                hash: hash,
+                height: height,
+            },
+            utxos: HashMap::new(),
+            undo: VecDeque::new(),
+        }
+    }
+
+    /// Watches `script`. Outputs in already processed blocks are not
+    /// picked up.
+    pub fn watch_script(&mut self, script: Script) {
+        self.scripts.insert(script);
+    }
+
+    /// Watches the script of `address`. Outputs in already processed blocks
+    /// are not picked up.
+    pub fn watch_address(&mut self, address: &Address) {
+        self.watch_script(address.script_pubkey());
+    }
+
+    /// The last processed block.
+    pub fn tip(&self) -> &BlockRef {
+        &self.tip
+    }
+
+    /// The unspent outputs paying to watched scripts.
+    pub fn utxos(&self) -> Vec<&Utxo> {
+        self.utxos.values().collect()
+    }
+
+    /// The total value of the unspent outputs, in satoshis.
+    pub fn balance(&self) -> u64 {
+        self.utxos.values().map(|u| u.value).sum()
+    }
+
+    /// Processes every block up to the tip of the active chain, reverting
+    /// disconnected blocks.
+    pub fn sync(&mut self, rpc: &BitcoinRpc) -> UtxoResult<()> {
+        let mut follower = ChainFollower::new(rpc, self.tip.height, self.tip.hash);
+
+        while let Some(event) = follower.poll()? {
+            match event {
+                Event::BlockConnected(block) => self.connect(&block, follower.tip().clone()),
+                Event::BlockDisconnected(hash) => self.disconnect(hash, follower.tip().clone())?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn connect(&mut self, block: &Block, tip: BlockRef) {
+        let mut undo = BlockUndo {
+            hash: tip.hash,
+            created: Vec::new(),
+            spent: Vec::new(),
+        };
+
+        for tx in &block.txdata {
+            for input in &tx.input {
+                if let Some(utxo) = self.utxos.remove(&input.previous_output) {
+                    undo.spent.push(utxo);
+                }
+            }
+
+            let txid = tx.txid();
+            for (vout, output) in tx.output.iter().enumerate() {
+                if !self.scripts.contains(&output.script_pubkey) {
+                    continue;
+                }
+
+                let outpoint = OutPoint {
+                    txid: txid,
+                    vout: vout as u32,
+                };
+                self.utxos.insert(
+                    outpoint,
+                    Utxo {
+                        outpoint: outpoint,
+                        value: output.value,
+                        script_pubkey: output.script_pubkey.clone(),
+                        height: tip.height,
+                    },
+                );
+                undo.created.push(outpoint);
+            }
+        }
+
+        self.undo.push_back(undo);
+        if self.undo.len() > MAX_REORG_DEPTH {
+            self.undo.pop_front();
+        }
+        self.tip = tip;
+    }
+
+    fn disconnect(&mut self, hash: BlockHash, tip: BlockRef) -> UtxoResult<()> {
+        match self.undo.back() {
+            Some(undo) if undo.hash == hash => {}
+            _ => return Err(Error::ReorgTooDeep { hash: hash }),
+        }
+        let undo = self.undo.pop_back().unwrap();
+
+        // In reverse order of `connect`, so an output both created and
+        // spent by the block ends up removed.
+        for utxo in undo.spent {
+            self.utxos.insert(utxo.outpoint, utxo);
+        }
+        for outpoint in &undo.created {
+            self.utxos.remove(outpoint);
+        }
+        self.tip = tip;
+
+        Ok(())
+    }
+
+    /// Saves the state to `path`, replacing it atomically.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> UtxoResult<()> {
+        let path = path.as_ref();
+        let tmp = path.with_extension("tmp");
+
+        let mut file = File::create(&tmp)?;
+        serde_json::to_writer(&mut file, &self.to_state())?;
+        file.sync_all()?;
+        fs::rename(&tmp, path)?;
+        sync_dir(path)?;
+
+        Ok(())
+    }
+
+    /// Loads a state previously saved with [`save`][].
+    ///
+    /// [`save`]: #method.save
+    pub fn load<P: AsRef<Path>>(path: P) -> UtxoResult<Self> {
+        let state: State = serde_json::from_reader(File::open(path)?)?;
+        UtxoTracker::from_state(state).ok_or(Error::CorruptState)
+    }
+
+    fn to_state(&self) -> State {
+        State {
+            scripts: self.scripts.iter().map(|s| bytes_to_hex(&s[..])).collect(),
+            tip_hash: self.tip.hash.to_string(),
+            tip_height: self.tip.height,
+            utxos: self.utxos.values().map(SerdeUtxo::from).collect(),
+            undo: self
+                .undo
+                .iter()
+                .map(|u| SerdeBlockUndo {
+                    hash: u.hash.to_string(),
+                    created: u.created.iter().map(SerdeOutPoint::from).collect(),
+                    spent: u.spent.iter().map(SerdeUtxo::from).collect(),
+                })
+                .collect(),
+        }
+    }
+
+    fn from_state(state: State) -> Option<Self> {
+        let mut scripts = HashSet::new();
+        for s in state.scripts {
+            scripts.insert(Script::from(hex_bytes(&s).ok()?));
+        }
+
+        let mut utxos = HashMap::new();
+        for u in state.utxos {
+            let utxo = u.into_utxo()?;
+            utxos.insert(utxo.outpoint, utxo);
+        }
+
+        let mut undo = VecDeque::new();
+        for u in state.undo {
+            undo.push_back(BlockUndo {
+                hash: BlockHash::from_hex(&u.hash).ok()?,
+                created: u
+                    .created
+                    .into_iter()
+                    .map(SerdeOutPoint::into_outpoint)
+                    .collect::<Option<_>>()?,
+                spent: u
+                    .spent
+                    .into_iter()
+                    .map(SerdeUtxo::into_utxo)
+                    .collect::<Option<_>>()?,
+            });
+        }
+
+        Some(UtxoTracker {
+            scripts: scripts,
+            tip: BlockRef {
+                hash: BlockHash::from_hex(&state.tip_hash).ok()?,
+                height: state.tip_height,
+            },
+            utxos: utxos,
+            undo: undo,
+        })
+    }
+}
+
+/// Flushes the directory entry of `path`, so a rename into it survives a
+/// crash.
+#[cfg(unix)]
+fn sync_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+/// Directories can't be opened as files on Windows, where `rename` is
+/// already durable once it returns.
+#[cfg(not(unix))]
+fn sync_dir(_path: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+#[derive(Deserialize, Serialize)]
+struct State {
+    scripts: Vec<String>,
+    tip_hash: String,
+    tip_height: u64,
+    utxos: Vec<SerdeUtxo>,
+    undo: Vec<SerdeBlockUndo>,
+}
+
+#[derive(Deserialize, Serialize)]
+struct SerdeOutPoint {
+    txid: String,
+    vout: u32,
+}
+
+impl<'a> From<&'a OutPoint> for SerdeOutPoint {
+    fn from(o: &'a OutPoint) -> SerdeOutPoint {
+        SerdeOutPoint {
+            txid: o.txid.to_string(),
+            vout: o.vout,
+        }
+    }
+}
+
+impl SerdeOutPoint {
+    fn into_outpoint(self) -> Option<OutPoint> {
+        Some(OutPoint {
+            txid: Sha256dHash::from_hex(&self.txid).ok()?,
+            vout: self.vout,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct SerdeUtxo {
+    outpoint: SerdeOutPoint,
+    value: u64,
+    script_pubkey: String,
+    height: u64,
+}
+
+impl<'a> From<&'a Utxo> for SerdeUtxo {
+    fn from(u: &'a Utxo) -> SerdeUtxo {
+        SerdeUtxo {
+            outpoint: SerdeOutPoint::from(&u.outpoint),
+            value: u.value,
+            script_pubkey: bytes_to_hex(&u.script_pubkey[..]),
+            height: u.height,
+        }
+    }
+}
+
+impl SerdeUtxo {
+    fn into_utxo(self) -> Option<Utxo> {
+        Some(Utxo {
+            outpoint: self.outpoint.into_outpoint()?,
+            value: self.value,
+            script_pubkey: Script::from(hex_bytes(&self.script_pubkey).ok()?),
+            height: self.height,
+        })
+    }
+}
+
+#[derive(Deserialize, Serialize)]
+struct SerdeBlockUndo {
+    hash: String,
+    created: Vec<SerdeOutPoint>,
+    spent: Vec<SerdeUtxo>,
+}
+
+pub type UtxoResult<T> = Result<T, Error>;
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Rpc { err: e }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Error {
+        Error::Io { err: e }
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Error {
+        Error::Format { err: e }
+    }
+}
+
+/// The tracker error type
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// Following the chain failed.
+    #[fail(display = "failed to follow the chain")]
+    Rpc {
+        #[cause]
+        err: ::Error,
+    },
+    /// Reading or writing the state failed.
+    #[fail(display = "failed to access the tracker state")]
+    Io {
+        #[cause]
+        err: io::Error,
+    },
+    /// The state could not be (de)serialized.
+    #[fail(display = "failed to (de)serialize the tracker state")]
+    Format {
+        #[cause]
+        err: serde_json::Error,
+    },
+    /// The loaded state contains invalid hashes or scripts.
+    #[fail(display = "the tracker state is corrupt")]
+    CorruptState,
+    /// A block deeper than the kept undo data was disconnected.
+    #[fail(display = "reorg too deep to revert block {}", hash)]
+    ReorgTooDeep { hash: BlockHash },
+}
+
+#[cfg(test)]
+mod tests {
+    use bitcoin::blockdata::transaction::Transaction;
+
+    use test_utils::{block, tx};
+
+    use super::*;
+
+    fn block_ref(height: u64) -> BlockRef {
+        BlockRef {
+            hash: Sha256dHash::from_data(&[height as u8]).into(),
+            height: height,
+        }
+    }
+
+    fn outpoint(tx: &Transaction, vout: u32) -> OutPoint {
+        OutPoint {
+            txid: tx.txid(),
+            vout: vout,
+        }
+    }
+
+    #[test]
+    fn disconnect_reverts_connect() {
+        let watched = Script::from(vec![0x51]);
+        let other = Script::from(vec![0x52]);
+        let mut tracker = UtxoTracker::new(vec![watched.clone()], 0, block_ref(0).hash);
+
+        // Block 1 pays the watched script.
+        let funding = tx(&[], vec![], &[(&watched, 50_000)]);
+        tracker.connect(&block(vec![funding.clone()]), block_ref(1));
+
+        // Block 2 spends it to the watched script, and spends that output
+        // in the same block.
+        let change = tx(&[outpoint(&funding, 0)], vec![], &[(&watched, 40_000)]);
+        let spend = tx(&[outpoint(&change, 0)], vec![], &[(&other, 30_000)]);
+        tracker.connect(&block(vec![change.clone(), spend]), block_ref(2));
+        assert!(tracker.utxos().is_empty());
+
+        tracker.disconnect(block_ref(2).hash, block_ref(1)).unwrap();
+        let utxos = tracker.utxos();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint, outpoint(&funding, 0));
+        assert_eq!(tracker.balance(), 50_000);
+        assert_eq!(tracker.tip().height, 1);
+
+        tracker.disconnect(block_ref(1).hash, block_ref(0)).unwrap();
+        assert!(tracker.utxos().is_empty());
+
+        // Nothing left to revert.
+        assert!(tracker.disconnect(block_ref(0).hash, block_ref(0)).is_err());
+    }
+
+    #[test]
+    fn disconnect_wrong_block_keeps_undo() {
+        let watched = Script::from(vec![0x51]);
+        let mut tracker = UtxoTracker::new(vec![watched.clone()], 0, block_ref(0).hash);
+
+        let funding = tx(&[], vec![], &[(&watched, 50_000)]);
+        tracker.connect(&block(vec![funding.clone()]), block_ref(1));
+
+        // Block 2 was never connected, so its undo record can't be the last.
+        assert!(tracker.disconnect(block_ref(2).hash, block_ref(1)).is_err());
+        assert_eq!(tracker.tip().height, 1);
+        assert_eq!(tracker.balance(), 50_000);
+
+        tracker.disconnect(block_ref(1).hash, block_ref(0)).unwrap();
+        assert!(tracker.utxos().is_empty());
+    }
+}
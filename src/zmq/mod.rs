@@ -0,0 +1,19 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+//! ZMQ related RPC result types and, with the `zmq` feature enabled, a
+//! subscriber for the notifications published by bitcoind.
+
+#[doc(inline)]
+pub use bitcoin_rpc_json::zmq::*;
+
+#[cfg(feature = "zmq")]
+mod subscriber;
+
+#[cfg(feature = "zmq")]
+pub use self::subscriber::*;
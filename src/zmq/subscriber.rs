@@ -0,0 +1,472 @@
+// Copyright 2018 Jean Pierre Dudey <jeandudey@hotmail.com>
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::collections::{HashMap, HashSet};
+
+use bitcoin::blockdata::block::Block;
+use bitcoin::blockdata::transaction::Transaction;
+use bitcoin::network::serialize::deserialize;
+use bitcoin::util::hash::Sha256dHash;
+
+use libzmq;
+
+use hashes::{BlockHash, Txid};
+use {bytes_to_hex, u32_from_le, u64_from_le, BitcoinRpc};
+
+/// A notification topic published by bitcoind.
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Hash)]
+pub enum Topic {
+    /// Serialized blocks ("rawblock")
+    RawBlock,
+    /// Serialized transactions ("rawtx")
+    RawTx,
+    /// Block hashes ("hashblock")
+    HashBlock,
+    /// Transaction ids ("hashtx")
+    HashTx,
+    /// Block and mempool sequence events ("sequence")
+    Sequence,
+}
+
+impl Topic {
+    /// The topic name as sent on the wire.
+    pub fn as_str(&self) -> &'static str {
+        match *self {
+            Topic::RawBlock => "rawblock",
+            Topic::RawTx => "rawtx",
+            Topic::HashBlock => "hashblock",
+            Topic::HashTx => "hashtx",
+            Topic::Sequence => "sequence",
+        }
+    }
+
+    /// Parses a notification type as returned by "getzmqnotifications",
+    /// e.g. "pubrawblock".
+    pub fn from_notification_type(s: &str) -> Option<Topic> {
+        if s.starts_with("pub") {
+            Topic::from_bytes(s[3..].as_bytes())
+        } else {
+            None
+        }
+    }
+
+    fn from_bytes(b: &[u8]) -> Option<Topic> {
+        match b {
+            b"rawblock" => Some(Topic::RawBlock),
+            b"rawtx" => Some(Topic::RawTx),
+            b"hashblock" => Some(Topic::HashBlock),
+            b"hashtx" => Some(Topic::HashTx),
+            b"sequence" => Some(Topic::Sequence),
+            _ => None,
+        }
+    }
+}
+
+/// An event of the "sequence" topic.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SequenceEvent {
+    /// A block was connected to the active chain
+    BlockConnected(BlockHash),
+    /// A block was disconnected from the active chain
+    BlockDisconnected(BlockHash),
+    /// A transaction was added to the mempool
+    TxAdded { txid: Txid, mempool_sequence: u64 },
+    /// A transaction was removed from the mempool
+    TxRemoved { txid: Txid, mempool_sequence: u64 },
+}
+
+/// A decoded notification.
+#[derive(Debug, Clone)]
+pub enum Notification {
+    RawBlock(Block),
+    RawTx(Transaction),
+    HashBlock(BlockHash),
+    HashTx(Txid),
+    Sequence(SequenceEvent),
+}
+
+/// A notification together with its publisher sequence number.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// Endpoint the notification was received from
+    pub endpoint: String,
+    /// Topic the notification was received on
+    pub topic: Topic,
+    /// Sequence number assigned by the publisher
+    pub sequence: u32,
+    /// Number of messages of this topic from this endpoint that were lost
+    /// before this one
+    pub missed: u32,
+    /// The decoded notification
+    pub notification: Notification,
+}
+
+impl Message {
+    /// Returns `true` if messages were lost before this one.
+    pub fn has_gap(&self) -> bool {
+        self.missed != 0
+    }
+}
+
+pub type ZmqResult<T> = Result<T, Error>;
+
+/// A socket connected to a single endpoint.
+struct Connection {
+    endpoint: String,
+    socket: libzmq::Socket,
+    topics: HashSet<Topic>,
+}
+
+/// Subscribes to the ZMQ notifications published by bitcoind.
+///
+/// Every endpoint gets its own socket, as each publisher numbers the
+/// messages of a topic on its own, so lost messages are counted per
+/// endpoint and topic.
+pub struct Subscriber {
+    // Must outlive the sockets.
+    context: libzmq::Context,
+    connections: Vec<Connection>,
+    // Connection polled first, so a busy endpoint can't starve the others.
+    next: usize,
+    sequences: SequenceTracker,
+}
+
+impl Subscriber {
+    /// Creates a subscriber that is not connected to any endpoint.
+    pub fn new() -> Subscriber {
+        Subscriber {
+            context: libzmq::Context::new(),
+            connections: Vec::new(),
+            next: 0,
+            sequences: SequenceTracker::default(),
+        }
+    }
+
+    /// Creates a subscriber for every notification reported by
+    /// "getzmqnotifications" that this subscriber understands.
+    pub fn from_rpc(rpc: &BitcoinRpc) -> ZmqResult<Subscriber> {
+        let notifications = rpc.getzmqnotifications()?;
+
+        let mut subscriber = Subscriber::new();
+        for n in notifications {
+            if let Some(topic) = Topic::from_notification_type(&n.notification_type) {
+                subscriber.subscribe(&n.address, topic)?;
+            }
+        }
+
+        if subscriber.connections.is_empty() {
+            return Err(Error::NoNotifications);
+        }
+
+        Ok(subscriber)
+    }
+
+    /// Connects to `endpoint` (if not already connected) and subscribes to
+    /// `topic`.
+    pub fn subscribe(&mut self, endpoint: &str, topic: Topic) -> ZmqResult<()> {
+        let index = match self.connections.iter().position(|c| c.endpoint == endpoint) {
+            Some(index) => index,
+            None => {
+                let socket = self.context.socket(libzmq::SUB)?;
+                socket.connect(endpoint)?;
+                self.connections.push(Connection {
+                    endpoint: endpoint.to_owned(),
+                    socket: socket,
+                    topics: HashSet::new(),
+                });
+                self.connections.len() - 1
+            }
+        };
+
+        let connection = &mut self.connections[index];
+        if connection.topics.insert(topic) {
+            connection.socket.set_subscribe(topic.as_str().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    /// Blocks until the next notification is received from any endpoint.
+    /// Fails with `Error::NoNotifications` if nothing is subscribed.
+    pub fn recv(&mut self) -> ZmqResult<Message> {
+        if self.connections.is_empty() {
+            return Err(Error::NoNotifications);
+        }
+
+        let index = self.poll()?;
+        let connection = &self.connections[index];
+        let parts = connection.socket.recv_multipart(0)?;
+        if parts.len() != 3 {
+            return Err(Error::MalformedMessage { topic: None });
+        }
+
+        let topic =
+            Topic::from_bytes(&parts[0]).ok_or_else(|| Error::MalformedMessage { topic: None })?;
+        let malformed = || Error::MalformedMessage { topic: Some(topic) };
+
+        if parts[2].len() != 4 {
+            return Err(malformed());
+        }
+        let sequence = u32_from_le(&parts[2]);
+
+        let missed = self.sequences.missed(&connection.endpoint, topic, sequence);
+
+        let body = &parts[1];
+        let notification = match topic {
+            Topic::RawBlock => Notification::RawBlock(deserialize(body).map_err(|_e| malformed())?),
+            Topic::RawTx => Notification::RawTx(deserialize(body).map_err(|_e| malformed())?),
+            Topic::HashBlock => {
+                Notification::HashBlock(hash_from_bytes(body).ok_or_else(&malformed)?)
+            }
+            Topic::HashTx => Notification::HashTx(hash_from_bytes(body).ok_or_else(&malformed)?),
+            Topic::Sequence => Notification::Sequence(sequence_event(body).ok_or_else(&malformed)?),
+        };
+
+        Ok(Message {
+            endpoint: connection.endpoint.clone(),
+            topic: topic,
+            sequence: sequence,
+            missed: missed,
+            notification: notification,
+        })
+    }
+
+    /// Waits until a connection has a message and returns its index,
+    /// taking turns between the connections with messages.
+    fn poll(&mut self) -> ZmqResult<usize> {
+        loop {
+            let readable: Vec<bool> = {
+                let mut items: Vec<_> = self
+                    .connections
+                    .iter()
+                    .map(|c| c.socket.as_poll_item(libzmq::POLLIN))
+                    .collect();
+                libzmq::poll(&mut items, -1)?;
+                items.iter().map(|item| item.is_readable()).collect()
+            };
+
+            let count = readable.len();
+            for i in 0..count {
+                let index = (self.next + i) % count;
+                if readable[index] {
+                    self.next = (index + 1) % count;
+                    return Ok(index);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Subscriber {
+    fn default() -> Subscriber {
+        Subscriber::new()
+    }
+}
+
+/// The last sequence number received, by endpoint and topic.
+#[derive(Debug, Default)]
+struct SequenceTracker {
+    last: HashMap<(String, Topic), u32>,
+}
+
+impl SequenceTracker {
+    /// Records `sequence` and returns the number of messages lost since the
+    /// previous one of `topic` from `endpoint`. Numbers wrap around at
+    /// `u32::MAX`; the first message of a topic counts as no loss.
+    fn missed(&mut self, endpoint: &str, topic: Topic, sequence: u32) -> u32 {
+        match self.last.insert((endpoint.to_owned(), topic), sequence) {
+            Some(last) => sequence.wrapping_sub(last).wrapping_sub(1),
+            None => 0,
+        }
+    }
+}
+
+/// Hashes are published in the same (reversed) byte order they are
+/// displayed in.
+fn hash_from_bytes<H: From<Sha256dHash>>(bytes: &[u8]) -> Option<H> {
+    if bytes.len() != 32 {
+        return None;
+    }
+
+    Sha256dHash::from_hex(&bytes_to_hex(bytes))
+        .ok()
+        .map(H::from)
+}
+
+fn sequence_event(body: &[u8]) -> Option<SequenceEvent> {
+    if body.len() < 33 {
+        return None;
+    }
+
+    let hash: Sha256dHash = hash_from_bytes(&body[..32])?;
+    let mempool_sequence = || {
+        if body.len() == 41 {
+            Some(u64_from_le(&body[33..]))
+        } else {
+            None
+        }
+    };
+
+    match body[32] {
+        b'C' if body.len() == 33 => Some(SequenceEvent::BlockConnected(hash.into())),
+        b'D' if body.len() == 33 => Some(SequenceEvent::BlockDisconnected(hash.into())),
+        b'A' => Some(SequenceEvent::TxAdded {
+            txid: hash.into(),
+            mempool_sequence: mempool_sequence()?,
+        }),
+        b'R' => Some(SequenceEvent::TxRemoved {
+            txid: hash.into(),
+            mempool_sequence: mempool_sequence()?,
+        }),
+        _ => None,
+    }
+}
+
+impl From<libzmq::Error> for Error {
+    fn from(e: libzmq::Error) -> Error {
+        Error::Zmq { err: e }
+    }
+}
+
+impl From<::Error> for Error {
+    fn from(e: ::Error) -> Error {
+        Error::Rpc { err: e }
+    }
+}
+
+/// The subscriber error type
+#[derive(Debug, Fail)]
+pub enum Error {
+    /// A ZMQ socket operation failed.
+    #[fail(display = "ZMQ socket error")]
+    Zmq {
+        #[cause]
+        err: libzmq::Error,
+    },
+    /// Discovering the notification endpoints failed.
+    #[fail(display = "failed to query ZMQ notifications")]
+    Rpc {
+        #[cause]
+        err: ::Error,
+    },
+    /// The node has no notifications enabled that we can subscribe to.
+    #[fail(display = "no supported ZMQ notifications are enabled")]
+    NoNotifications,
+    /// The received message could not be decoded.
+    #[fail(display = "ZMQ {:?} message is malformed", topic)]
+    MalformedMessage { topic: Option<Topic> },
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "000000000019d6689c085ae165831e934ff763ae46a2a6c172b3f1b60a8ce26f";
+
+    fn hash_bytes() -> Vec<u8> {
+        (0..32)
+            .map(|i| u8::from_str_radix(&HASH[2 * i..2 * i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn body(hash: &[u8], label: u8, mempool_sequence: Option<u64>) -> Vec<u8> {
+        let mut body = hash.to_vec();
+        body.push(label);
+        if let Some(seq) = mempool_sequence {
+            body.extend((0..8).map(|i| (seq >> (8 * i)) as u8));
+        }
+        body
+    }
+
+    #[test]
+    fn hash_from_bytes_keeps_display_order() {
+        let hash: BlockHash = hash_from_bytes(&hash_bytes()).unwrap();
+        assert_eq!(hash.to_string(), HASH);
+
+        assert!(hash_from_bytes::<Txid>(&hash_bytes()[..31]).is_none());
+        assert!(hash_from_bytes::<Txid>(&[0; 33]).is_none());
+    }
+
+    #[test]
+    fn sequence_events() {
+        let hash = hash_bytes();
+        let block = BlockHash::from_hex(HASH).unwrap();
+        let txid = Txid::from_hex(HASH).unwrap();
+
+        let cases = vec![
+            (
+                body(&hash, b'C', None),
+                Some(SequenceEvent::BlockConnected(block)),
+            ),
+            (
+                body(&hash, b'D', None),
+                Some(SequenceEvent::BlockDisconnected(block)),
+            ),
+            (
+                body(&hash, b'A', Some(0x0102_0304_0506_0708)),
+                Some(SequenceEvent::TxAdded {
+                    txid: txid,
+                    mempool_sequence: 0x0102_0304_0506_0708,
+                }),
+            ),
+            (
+                body(&hash, b'R', Some(7)),
+                Some(SequenceEvent::TxRemoved {
+                    txid: txid,
+                    mempool_sequence: 7,
+                }),
+            ),
+            // Block events carry no mempool sequence, tx events need one.
+            (body(&hash, b'C', Some(7)), None),
+            (body(&hash, b'A', None), None),
+            (body(&hash, b'X', None), None),
+            (body(&hash[..31], b'C', None), None),
+            (hash.clone(), None),
+        ];
+        for (body, expected) in cases {
+            assert_eq!(sequence_event(&body), expected, "{:?}", body);
+        }
+    }
+
+    #[test]
+    fn missed_messages() {
+        let a = "tcp://127.0.0.1:28332";
+        let b = "tcp://127.0.0.1:28333";
+        let mut tracker = SequenceTracker::default();
+
+        let cases = vec![
+            // The first message of a topic.
+            (a, Topic::HashBlock, 5, 0),
+            // In order.
+            (a, Topic::HashBlock, 6, 0),
+            // Two lost.
+            (a, Topic::HashBlock, 9, 2),
+            // Topics and endpoints are numbered separately.
+            (a, Topic::HashTx, 100, 0),
+            (b, Topic::HashBlock, 0, 0),
+            (b, Topic::HashBlock, 1, 0),
+            (a, Topic::HashBlock, 10, 0),
+            // Wrapping around.
+            (a, Topic::HashTx, u32::MAX, u32::MAX - 101),
+            (a, Topic::HashTx, 0, 0),
+            (a, Topic::HashTx, 2, 1),
+            (b, Topic::HashBlock, u32::MAX, u32::MAX - 2),
+            (b, Topic::HashBlock, 1, 1),
+        ];
+        for (endpoint, topic, sequence, missed) in cases {
+            assert_eq!(
+                tracker.missed(endpoint, topic, sequence),
+                missed,
+                "{} {:?} {}",
+                endpoint,
+                topic,
+                sequence
+            );
+        }
+    }
+}